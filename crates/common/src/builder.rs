@@ -0,0 +1,216 @@
+//! Envelope construction helpers
+//!
+//! Every envelope built here gets a fresh [`new_id`](crate::id::new_id), the
+//! current protocol version and a timestamp taken from the same clock reading
+//! as the ID, both with millisecond precision.
+
+use crate::id;
+use crate::message::{
+    Envelope, HeartbeatPayload, MicExpiredPayload, MicGrantPayload, MicGrantSnapshotPayload,
+    MicRevokePayload, ModerationDecisionPayload, ModerationRequestPayload, Payload,
+    RedactionPayload, RejectPayload, ResultContent, ResultPayload, SayPayload, Sender, SenderKind,
+    SummaryPayload, TaskPayload, PROTOCOL_VERSION,
+};
+
+/// Builder for envelopes sent by one sender into one room
+///
+/// Cheap to clone, so components typically keep one around and call a typed
+/// constructor per outgoing message.
+#[derive(Debug, Clone)]
+pub struct EnvelopeBuilder {
+    room_id: String,
+    from: Sender,
+    correlation_id: Option<String>,
+    causation_id: Option<String>,
+}
+
+impl EnvelopeBuilder {
+    pub fn new(room_id: impl Into<String>, from: Sender) -> Self {
+        Self {
+            room_id: room_id.into(),
+            from,
+            correlation_id: None,
+            causation_id: None,
+        }
+    }
+
+    /// Builder for envelopes sent in response to `cause`
+    ///
+    /// They join the thread of `cause` and record it as their causation.
+    pub fn caused_by(&self, cause: &Envelope) -> Self {
+        self.linked(cause.thread_id(), &cause.id)
+    }
+
+    /// Like [`caused_by`](Self::caused_by), for a cause known only by its IDs
+    pub fn linked(
+        &self,
+        correlation_id: impl Into<String>,
+        causation_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            correlation_id: Some(correlation_id.into()),
+            causation_id: Some(causation_id.into()),
+            ..self.clone()
+        }
+    }
+
+    /// Build an envelope around an arbitrary payload
+    pub fn build(&self, payload: Payload) -> Envelope {
+        let id = id::new_id();
+        let ts = id::timestamp_millis(&id).unwrap_or_default();
+
+        Envelope {
+            id,
+            version: Some(PROTOCOL_VERSION),
+            room_id: self.room_id.clone(),
+            from: self.from.clone(),
+            ts,
+            correlation_id: self.correlation_id.clone(),
+            causation_id: self.causation_id.clone(),
+            payload,
+            signature: None,
+        }
+    }
+
+    pub fn say(&self, text: impl Into<String>) -> Envelope {
+        self.build(Payload::Say(SayPayload { text: text.into() }))
+    }
+
+    pub fn task(&self, payload: TaskPayload) -> Envelope {
+        self.build(Payload::Task(payload))
+    }
+
+    pub fn mic_grant(&self, payload: MicGrantPayload) -> Envelope {
+        self.build(Payload::MicGrant(payload))
+    }
+
+    pub fn mic_revoke(&self, payload: MicRevokePayload) -> Envelope {
+        self.build(Payload::MicRevoke(payload))
+    }
+
+    pub fn result(&self, task_id: impl Into<String>, content: ResultContent) -> Envelope {
+        self.build(Payload::Result(ResultPayload::new(task_id, content)))
+    }
+
+    pub fn reject(&self, payload: RejectPayload) -> Envelope {
+        self.build(Payload::Reject(payload))
+    }
+
+    pub fn heartbeat(&self, payload: HeartbeatPayload) -> Envelope {
+        self.build(Payload::Heartbeat(payload))
+    }
+
+    pub fn summary(&self, payload: SummaryPayload) -> Envelope {
+        self.build(Payload::Summary(payload))
+    }
+
+    pub fn mic_grant_snapshot(&self, payload: MicGrantSnapshotPayload) -> Envelope {
+        self.build(Payload::MicGrantSnapshot(payload))
+    }
+
+    pub fn mic_expired(&self, payload: MicExpiredPayload) -> Envelope {
+        self.build(Payload::MicExpired(payload))
+    }
+
+    pub fn redaction(&self, payload: RedactionPayload) -> Envelope {
+        self.build(Payload::Redaction(payload))
+    }
+
+    pub fn moderation_request(&self, payload: ModerationRequestPayload) -> Envelope {
+        self.build(Payload::ModerationRequest(payload))
+    }
+
+    pub fn moderation_decision(&self, payload: ModerationDecisionPayload) -> Envelope {
+        self.build(Payload::ModerationDecision(payload))
+    }
+}
+
+impl Envelope {
+    /// Start building envelopes for `room_id` sent by `from`
+    pub fn builder(room_id: impl Into<String>, from: Sender) -> EnvelopeBuilder {
+        EnvelopeBuilder::new(room_id, from)
+    }
+}
+
+impl Sender {
+    pub fn user(id: impl Into<String>) -> Self {
+        Self {
+            kind: SenderKind::User,
+            id: id.into(),
+        }
+    }
+
+    pub fn agent(id: impl Into<String>) -> Self {
+        Self {
+            kind: SenderKind::Agent,
+            id: id.into(),
+        }
+    }
+
+    pub fn system(id: impl Into<String>) -> Self {
+        Self {
+            kind: SenderKind::System,
+            id: id.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{AckContent, EnvelopeType, ResultMessageType};
+
+    #[test]
+    fn test_builder_unique_ids_same_second() {
+        let builder = Envelope::builder("room_1", Sender::agent("facilitator"));
+        let ack = builder.result(
+            "task_1",
+            ResultContent::Ack(AckContent {
+                text: "ok".to_string(),
+            }),
+        );
+        let revoke = builder.mic_revoke(MicRevokePayload {
+            task_id: "task_1".to_string(),
+            agent_id: "agent".to_string(),
+        });
+
+        assert_ne!(ack.id, revoke.id);
+        assert_eq!(ack.message_type(), EnvelopeType::Result);
+        assert_eq!(revoke.message_type(), EnvelopeType::MicRevoke);
+        assert_eq!(ack.version, Some(PROTOCOL_VERSION));
+        assert_eq!(Some(ack.ts), id::timestamp_millis(&ack.id));
+        let Payload::Result(result) = &ack.payload else {
+            panic!("expected result payload");
+        };
+        assert_eq!(result.message_type, ResultMessageType::Ack);
+    }
+
+    #[test]
+    fn test_caused_by_threads_envelopes() {
+        let say = Envelope::builder("room_1", Sender::user("alice")).say("check disk");
+        let facilitator = Envelope::builder("room_1", Sender::agent("facilitator"));
+
+        let task = facilitator.caused_by(&say).task(TaskPayload {
+            task_id: "task_1".to_string(),
+            goal: "df -h".to_string(),
+            format: None,
+            deadline: None,
+        });
+        assert_eq!(task.correlation_id.as_deref(), Some(say.id.as_str()));
+        assert_eq!(task.causation_id.as_deref(), Some(say.id.as_str()));
+
+        let result = Envelope::builder("room_1", Sender::agent("cmd"))
+            .caused_by(&task)
+            .result(
+                "task_1",
+                ResultContent::Ack(AckContent {
+                    text: "on it".to_string(),
+                }),
+            );
+        assert_eq!(result.thread_id(), say.thread_id());
+        assert_eq!(result.causation_id.as_deref(), Some(task.id.as_str()));
+
+        // The original builder is unaffected
+        assert_eq!(facilitator.say("hi").correlation_id, None);
+    }
+}
@@ -5,8 +5,11 @@
 pub enum AorError {
     Mqtt(String),
     Validation(String),
+    Schema(String),
+    UnsupportedVersion(String),
     PermissionDenied(String),
     Llm(String),
+    Signature(String),
 }
 
 impl std::fmt::Display for AorError {
@@ -14,11 +17,15 @@ impl std::fmt::Display for AorError {
         match self {
             AorError::Mqtt(msg) => write!(f, "MQTT error: {}", msg),
             AorError::Validation(msg) => write!(f, "Validation error: {}", msg),
+            AorError::Schema(msg) => write!(f, "Schema error: {}", msg),
+            AorError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported protocol version: {}", version)
+            }
             AorError::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
             AorError::Llm(msg) => write!(f, "LLM error: {}", msg),
+            AorError::Signature(msg) => write!(f, "Signature error: {}", msg),
         }
     }
 }
 
 impl std::error::Error for AorError {}
-
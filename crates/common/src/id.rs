@@ -0,0 +1,96 @@
+//! Globally unique, time-sortable message IDs
+//!
+//! IDs follow the ULID layout: a 48-bit millisecond timestamp followed by 80
+//! random bits, encoded as 26 Crockford base32 characters. IDs generated in
+//! the same millisecond by one process are strictly increasing, and no
+//! coordination between processes is required.
+
+use crate::time;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+
+const ENCODING: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ID_LEN: usize = 26;
+const RANDOM_BITS: u32 = 80;
+const RANDOM_MASK: u128 = (1 << RANDOM_BITS) - 1;
+
+/// Last (timestamp, random) pair handed out, for monotonic generation
+static LAST: Mutex<(u64, u128)> = Mutex::new((0, 0));
+
+/// Generate a new globally unique message ID
+pub fn new_id() -> String {
+    let mut last = LAST.lock().unwrap();
+    let now = time::now_millis();
+
+    let (ms, random) = if now <= last.0 {
+        // Same millisecond (or clock went backwards): increment to stay ordered
+        match last.1.checked_add(1).filter(|r| *r <= RANDOM_MASK) {
+            Some(random) => (last.0, random),
+            None => (last.0 + 1, random_bits()),
+        }
+    } else {
+        (now, random_bits())
+    };
+
+    *last = (ms, random);
+    encode(((ms as u128) << RANDOM_BITS) | random)
+}
+
+/// Millisecond timestamp embedded in an ID generated by [`new_id`]
+pub fn timestamp_millis(id: &str) -> Option<u64> {
+    if id.len() != ID_LEN {
+        return None;
+    }
+
+    let mut value: u128 = 0;
+    for c in id.bytes() {
+        let digit = ENCODING.iter().position(|e| *e == c.to_ascii_uppercase())?;
+        value = value.checked_mul(32)? | digit as u128;
+    }
+
+    Some((value >> RANDOM_BITS) as u64)
+}
+
+fn random_bits() -> u128 {
+    // RandomState is seeded from OS randomness, so no extra dependency is needed
+    let mut value: u128 = 0;
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(value);
+        value = (value << 64) | hasher.finish() as u128;
+    }
+    value & RANDOM_MASK
+}
+
+fn encode(mut value: u128) -> String {
+    let mut out = [0u8; ID_LEN];
+    for slot in out.iter_mut().rev() {
+        *slot = ENCODING[(value & 0x1f) as usize];
+        value >>= 5;
+    }
+    String::from_utf8(out.to_vec()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_unique_and_ordered() {
+        let ids: Vec<String> = (0..1000).map(|_| new_id()).collect();
+        for pair in ids.windows(2) {
+            assert!(pair[0] < pair[1], "{} !< {}", pair[0], pair[1]);
+        }
+        assert!(ids.iter().all(|id| id.len() == ID_LEN));
+    }
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        let before = time::now_millis();
+        let id = new_id();
+        let ts = timestamp_millis(&id).unwrap();
+        assert!(ts >= before && ts <= time::now_millis() + 1);
+        assert_eq!(timestamp_millis("not-an-id"), None);
+    }
+}
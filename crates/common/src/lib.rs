@@ -1,21 +1,36 @@
 //! Common types and utilities shared across Agent Ops Room components
 //!
 //! This crate contains:
-//! - Message envelope and payload types per AOR spec v0.1
-//! - Topic naming conventions
+//! - Message envelope and payload types per AOR spec v0.2
+//! - Envelope builder, unique IDs and clock helpers
+//! - Topic naming conventions and validated room/agent IDs
 //! - Shared errors
 //! - LLM client utilities
 //! - Message history/memory for conversation context
+//! - MQTT component runtime (heartbeats, reconnects, shutdown, dispatch)
+//! - JSON Schema export and validation for the protocol types
+//! - Ed25519 envelope signing and verification
 
+pub mod builder;
 pub mod error;
+pub mod id;
 #[cfg(feature = "llm")]
 pub mod llm;
 #[cfg(feature = "llm")]
 pub mod memory;
 pub mod message;
+pub mod names;
+#[cfg(feature = "runtime")]
+pub mod runtime;
+#[cfg(feature = "schema")]
+pub mod schema;
+#[cfg(feature = "signing")]
+pub mod signing;
+pub mod time;
 pub mod topics;
 
 // Re-export commonly used types
+pub use builder::EnvelopeBuilder;
 pub use error::AorError;
 #[cfg(feature = "llm")]
 pub use llm::{
@@ -25,6 +40,13 @@ pub use llm::{
 #[cfg(feature = "llm")]
 pub use memory::MessageHistory;
 pub use message::{
-    Envelope, EnvelopeType, MicGrantPayload, MicRevokePayload, Payload, RejectPayload,
-    ResultMessageType, ResultPayload, SayPayload, Sender, SenderKind, SummaryPayload, TaskPayload,
+    Envelope, EnvelopeType, HeartbeatPayload, MicExpiredPayload, MicExpiryReason, MicGrantPayload,
+    MicGrantSnapshotPayload, MicGrantState, MicRevokePayload, ModerationDecision,
+    ModerationDecisionPayload, ModerationRequestPayload, Payload, ProtocolVersion, Redaction,
+    RedactionAction, RedactionPayload, RejectPayload, ResultMessageType, ResultPayload, SayPayload,
+    Sender, SenderKind, SummaryPayload, TaskPayload, PROTOCOL_VERSION,
 };
+pub use names::{AgentId, RoomId};
+#[cfg(feature = "signing")]
+pub use signing::{KeyRegistry, SignatureError, Signer};
+pub use topics::Topic;
@@ -1,8 +1,7 @@
 //! Message history and conversation memory for LLM context
 
 use crate::llm::ChatMessage;
-use crate::message::{Envelope, EnvelopeType, SayPayload, SenderKind};
-use serde_json::Value;
+use crate::message::{Envelope, Payload, ResultContent, SenderKind};
 use std::collections::VecDeque;
 
 /// Message history tracker with configurable size
@@ -51,10 +50,9 @@ impl MessageHistory {
                     SenderKind::Agent => "assistant",
                 };
 
-                let content = match envelope.message_type {
-                    EnvelopeType::Say => extract_say_text(&envelope.payload)
-                        .map(|text| format!("{}: {}", envelope.from.id, text)),
-                    EnvelopeType::Result => extract_result_text(&envelope.payload)
+                let content = match &envelope.payload {
+                    Payload::Say(say) => Some(format!("{}: {}", envelope.from.id, say.text)),
+                    Payload::Result(result) => extract_result_text(&result.content)
                         .map(|text| format!("{}: {}", envelope.from.id, text)),
                     _ => None,
                 };
@@ -83,9 +81,8 @@ impl MessageHistory {
                     SenderKind::Agent => "assistant",
                 };
 
-                let content = match envelope.message_type {
-                    EnvelopeType::Say => extract_say_text(&envelope.payload)
-                        .map(|text| format!("{}: {}", envelope.from.id, text)),
+                let content = match &envelope.payload {
+                    Payload::Say(say) => Some(format!("{}: {}", envelope.from.id, say.text)),
                     _ => None,
                 };
 
@@ -108,24 +105,21 @@ impl Default for MessageHistory {
 
 // Helper functions to extract content from payloads
 
-fn extract_say_text(payload: &Value) -> Option<String> {
-    serde_json::from_value::<SayPayload>(payload.clone())
-        .ok()
-        .map(|p| p.text)
-}
-
-fn extract_result_text(payload: &Value) -> Option<String> {
-    payload
-        .get("content")?
-        .get("text")
-        .and_then(|v| v.as_str())
-        .map(String::from)
+fn extract_result_text(content: &ResultContent) -> Option<String> {
+    match content {
+        ResultContent::Ack(ack) => Some(ack.text.clone()),
+        ResultContent::Progress(progress) => Some(progress.text.clone()),
+        ResultContent::Finding(finding) => finding.text.clone(),
+        ResultContent::Risk(risk) => Some(risk.text.clone()),
+        ResultContent::Result(result) => Some(result.text.clone()),
+        ResultContent::ClarifyingQuestion(_) | ResultContent::ArtifactLink(_) => None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::message::Sender;
+    use crate::message::{SayPayload, Sender};
 
     #[test]
     fn test_message_history_capacity() {
@@ -134,14 +128,19 @@ mod tests {
         for i in 0..5 {
             let envelope = Envelope {
                 id: format!("msg_{}", i),
-                message_type: EnvelopeType::Say,
+                version: None,
                 room_id: "test".to_string(),
                 from: Sender {
                     kind: SenderKind::User,
                     id: "user1".to_string(),
                 },
                 ts: i as u64,
-                payload: serde_json::json!({"text": format!("Message {}", i)}),
+                correlation_id: None,
+                causation_id: None,
+                payload: Payload::Say(SayPayload {
+                    text: format!("Message {}", i),
+                }),
+                signature: None,
             };
             history.add(envelope);
         }
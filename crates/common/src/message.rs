@@ -1,27 +1,185 @@
-//! Message envelope and payload types per AOR spec v0.1
+//! Message envelope and payload types per AOR spec v0.2
 
-use serde::{Deserialize, Serialize};
+use crate::error::AorError;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 
 /// Canonical message envelope - ALL messages use this structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Envelope {
     /// Globally unique message ID
     pub id: String,
-    /// Envelope message type
-    #[serde(rename = "type")]
-    pub message_type: EnvelopeType,
+    /// Protocol version the sender speaks (absent on v0.1 envelopes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<ProtocolVersion>,
     /// Room identifier
     pub room_id: String,
     /// Sender information
     pub from: Sender,
-    /// Unix timestamp (seconds)
+    /// Unix timestamp (milliseconds); payload timestamps are in seconds
     pub ts: u64,
-    /// Type-specific payload
+    /// Conversation thread this envelope belongs to (ID of the thread's first envelope)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// ID of the envelope that directly caused this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub causation_id: Option<String>,
+    /// Envelope type and type-specific payload (`type` + `payload` on the wire)
+    #[serde(flatten)]
     pub payload: Payload,
+    /// Base64 Ed25519 signature by `from.id` over the rest of the envelope's
+    /// canonical JSON: keys sorted, no whitespace, and null members left out,
+    /// so optional fields sent as null sign the same as omitted ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl Envelope {
+    /// Decode an envelope from raw JSON bytes, reporting schema errors
+    ///
+    /// Envelopes from older compatible protocol versions are upgraded before
+    /// decoding; envelopes from an unsupported major version are refused with
+    /// `AorError::UnsupportedVersion`.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, AorError> {
+        let value: serde_json::Value =
+            serde_json::from_slice(bytes).map_err(|e| AorError::Schema(e.to_string()))?;
+        let value = upgrade(value)?;
+        serde_json::from_value(value).map_err(|e| AorError::Schema(e.to_string()))
+    }
+
+    /// Envelope message type, derived from the payload variant
+    pub fn message_type(&self) -> EnvelopeType {
+        self.payload.message_type()
+    }
+
+    /// Thread ID for grouping: the correlation ID, or this envelope's own ID
+    /// when it starts a thread
+    pub fn thread_id(&self) -> &str {
+        self.correlation_id.as_deref().unwrap_or(&self.id)
+    }
+
+    /// Protocol version of this envelope (v0.1 when the field is absent)
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.version.unwrap_or(LEGACY_PROTOCOL_VERSION)
+    }
+}
+
+/// AOR protocol version (`major.minor`)
+///
+/// Envelopes are compatible when their major version matches ours. Older minor
+/// versions are upgraded on decode; newer minor versions are accepted and any
+/// fields we don't know about are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProtocolVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+/// Protocol version spoken by this implementation
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 0, minor: 2 };
+
+/// Version assumed for envelopes that carry no `version` field
+pub const LEGACY_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 0, minor: 1 };
+
+impl ProtocolVersion {
+    /// Whether envelopes of this version can be decoded by this implementation
+    pub fn is_supported(&self) -> bool {
+        self.major == PROTOCOL_VERSION.major
+    }
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+impl std::str::FromStr for ProtocolVersion {
+    type Err = AorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AorError::Schema(format!("invalid protocol version: {:?}", s));
+        let (major, minor) = s.split_once('.').ok_or_else(invalid)?;
+        Ok(Self {
+            major: major.parse().map_err(|_| invalid())?,
+            minor: minor.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Serialize for ProtocolVersion {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProtocolVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Upgrade step from `from` to the next minor version, applied to raw JSON
+type UpgradeStep = (
+    ProtocolVersion,
+    fn(&mut serde_json::Map<String, serde_json::Value>),
+);
+
+/// Ordered upgrade steps; each runs on envelopes older than or equal to its version
+const UPGRADES: &[UpgradeStep] = &[
+    // 0.1 -> 0.2: `ts` moves from seconds to milliseconds; payload
+    // timestamps stay in seconds
+    (LEGACY_PROTOCOL_VERSION, |envelope| {
+        if let Some(ts) = envelope.get("ts").and_then(serde_json::Value::as_u64) {
+            envelope["ts"] = ts.saturating_mul(1000).into();
+        }
+    }),
+];
+
+/// Upgrade a raw envelope to the current protocol version's shape
+///
+/// Upgraded envelopes are stamped with the current version, so that one
+/// republished by a component such as the gateway isn't upgraded twice.
+/// Envelopes that need no upgrade keep their `version` as sent.
+pub fn upgrade(mut value: serde_json::Value) -> Result<serde_json::Value, AorError> {
+    let version = match value.get("version") {
+        None | Some(serde_json::Value::Null) => LEGACY_PROTOCOL_VERSION,
+        Some(serde_json::Value::String(v)) => v.parse()?,
+        Some(other) => {
+            return Err(AorError::Schema(format!(
+                "invalid protocol version: {}",
+                other
+            )))
+        }
+    };
+
+    if !version.is_supported() {
+        return Err(AorError::UnsupportedVersion(version.to_string()));
+    }
+
+    let Some(object) = value.as_object_mut() else {
+        // Not an envelope; left for decoding to report
+        return Ok(value);
+    };
+    let mut upgraded = false;
+    for (from, step) in UPGRADES {
+        if version <= *from {
+            step(object);
+            upgraded = true;
+        }
+    }
+    if upgraded {
+        object.insert("version".to_string(), PROTOCOL_VERSION.to_string().into());
+    }
+
+    Ok(value)
 }
 
 /// Envelope message types
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum EnvelopeType {
     Say,
@@ -32,10 +190,16 @@ pub enum EnvelopeType {
     Reject,
     Heartbeat,
     Summary,
+    MicGrantSnapshot,
+    MicExpired,
+    Redaction,
+    ModerationRequest,
+    ModerationDecision,
 }
 
 /// Sender information
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Sender {
     /// Sender category
     pub kind: SenderKind,
@@ -45,6 +209,7 @@ pub struct Sender {
 
 /// Sender categories
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum SenderKind {
     User,
@@ -56,17 +221,57 @@ pub enum SenderKind {
 pub type From = Sender;
 pub type FromKind = SenderKind;
 
-/// Type-specific payloads
-pub type Payload = serde_json::Value;
+/// Type-specific payloads, tagged by the envelope `type` field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum Payload {
+    Say(SayPayload),
+    Task(TaskPayload),
+    MicGrant(MicGrantPayload),
+    MicRevoke(MicRevokePayload),
+    Result(ResultPayload),
+    Reject(RejectPayload),
+    Heartbeat(HeartbeatPayload),
+    Summary(SummaryPayload),
+    MicGrantSnapshot(MicGrantSnapshotPayload),
+    MicExpired(MicExpiredPayload),
+    Redaction(RedactionPayload),
+    ModerationRequest(ModerationRequestPayload),
+    ModerationDecision(ModerationDecisionPayload),
+}
+
+impl Payload {
+    /// Envelope type corresponding to this payload
+    pub fn message_type(&self) -> EnvelopeType {
+        match self {
+            Payload::Say(_) => EnvelopeType::Say,
+            Payload::Task(_) => EnvelopeType::Task,
+            Payload::MicGrant(_) => EnvelopeType::MicGrant,
+            Payload::MicRevoke(_) => EnvelopeType::MicRevoke,
+            Payload::Result(_) => EnvelopeType::Result,
+            Payload::Reject(_) => EnvelopeType::Reject,
+            Payload::Heartbeat(_) => EnvelopeType::Heartbeat,
+            Payload::Summary(_) => EnvelopeType::Summary,
+            Payload::MicGrantSnapshot(_) => EnvelopeType::MicGrantSnapshot,
+            Payload::MicExpired(_) => EnvelopeType::MicExpired,
+            Payload::Redaction(_) => EnvelopeType::Redaction,
+            Payload::ModerationRequest(_) => EnvelopeType::ModerationRequest,
+            Payload::ModerationDecision(_) => EnvelopeType::ModerationDecision,
+        }
+    }
+}
 
 /// Free-form human chat
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SayPayload {
     pub text: String,
 }
 
 /// Authoritative instruction to perform work
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct TaskPayload {
     pub task_id: String,
     pub goal: String,
@@ -78,24 +283,71 @@ pub struct TaskPayload {
 
 /// Permission to speak publicly
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MicGrantPayload {
     pub task_id: String,
     pub agent_id: String,
     pub max_messages: u32,
     pub allowed_message_types: Vec<ResultMessageType>,
+    /// Unix timestamp (seconds) after which the grant no longer applies
     pub expires_at: u64,
 }
 
 /// Structured agent disclosure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// `content` is decoded according to its sibling `message_type`, so each
+/// result type is validated against its own schema.
+#[derive(Debug, Clone, Serialize)]
 pub struct ResultPayload {
     pub task_id: String,
     pub message_type: ResultMessageType,
     pub content: ResultContent,
+    /// Changes the Gateway made before republishing; empty when unchanged
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redactions: Vec<Redaction>,
+}
+
+impl ResultPayload {
+    /// Create a result payload, deriving `message_type` from the content variant
+    pub fn new(task_id: impl Into<String>, content: ResultContent) -> Self {
+        Self {
+            task_id: task_id.into(),
+            message_type: content.message_type(),
+            content,
+            redactions: Vec::new(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ResultPayload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct RawResultPayload {
+            task_id: String,
+            message_type: ResultMessageType,
+            content: serde_json::Value,
+            #[serde(default)]
+            redactions: Vec<Redaction>,
+        }
+
+        let raw = RawResultPayload::deserialize(deserializer)?;
+        let content = ResultContent::decode(&raw.message_type, raw.content)
+            .map_err(serde::de::Error::custom)?;
+
+        Ok(Self {
+            task_id: raw.task_id,
+            message_type: raw.message_type,
+            content,
+            redactions: raw.redactions,
+        })
+    }
 }
 
 /// Result content types per AOR spec
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Serialized without a tag; the variant is selected by the enclosing
+/// `ResultPayload.message_type` when decoding.
+#[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum ResultContent {
     Ack(AckContent),
@@ -107,26 +359,94 @@ pub enum ResultContent {
     ArtifactLink(ArtifactLinkContent),
 }
 
+impl ResultContent {
+    /// Decode content for the given result message type
+    pub fn decode(
+        message_type: &ResultMessageType,
+        value: serde_json::Value,
+    ) -> Result<Self, AorError> {
+        let invalid = |e: serde_json::Error| {
+            AorError::Schema(format!("invalid {} content: {}", message_type, e))
+        };
+
+        let content = match message_type {
+            ResultMessageType::Ack => {
+                ResultContent::Ack(serde_json::from_value(value).map_err(invalid)?)
+            }
+            ResultMessageType::ClarifyingQuestion => {
+                ResultContent::ClarifyingQuestion(serde_json::from_value(value).map_err(invalid)?)
+            }
+            ResultMessageType::Progress => {
+                ResultContent::Progress(serde_json::from_value(value).map_err(invalid)?)
+            }
+            ResultMessageType::Finding => {
+                let finding: FindingContent = serde_json::from_value(value).map_err(invalid)?;
+                if finding.text.is_none() && finding.bullets.is_none() {
+                    return Err(AorError::Schema(
+                        "invalid finding content: requires `text` or `bullets`".to_string(),
+                    ));
+                }
+                ResultContent::Finding(finding)
+            }
+            ResultMessageType::Risk => {
+                ResultContent::Risk(serde_json::from_value(value).map_err(invalid)?)
+            }
+            ResultMessageType::Result => {
+                ResultContent::Result(serde_json::from_value(value).map_err(invalid)?)
+            }
+            ResultMessageType::ArtifactLink => {
+                ResultContent::ArtifactLink(serde_json::from_value(value).map_err(invalid)?)
+            }
+        };
+
+        Ok(content)
+    }
+
+    /// Result message type this content belongs to
+    pub fn message_type(&self) -> ResultMessageType {
+        match self {
+            ResultContent::Ack(_) => ResultMessageType::Ack,
+            ResultContent::ClarifyingQuestion(_) => ResultMessageType::ClarifyingQuestion,
+            ResultContent::Progress(_) => ResultMessageType::Progress,
+            ResultContent::Finding(_) => ResultMessageType::Finding,
+            ResultContent::Risk(_) => ResultMessageType::Risk,
+            ResultContent::Result(_) => ResultMessageType::Result,
+            ResultContent::ArtifactLink(_) => ResultMessageType::ArtifactLink,
+        }
+    }
+}
+
 /// Ack content: acknowledges task acceptance
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct AckContent {
     pub text: String,
 }
 
 /// Clarifying question content: requests user input
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ClarifyingQuestionContent {
     pub question: String,
 }
 
 /// Progress content: lightweight status update
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ProgressContent {
     pub text: String,
 }
 
 /// Finding content: important intermediate discovery
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[cfg_attr(
+    feature = "schema",
+    schemars(extend("anyOf" = [
+        { "required": ["text"], "properties": { "text": { "type": "string" } } },
+        { "required": ["bullets"], "properties": { "bullets": { "type": "array" } } }
+    ]))
+)]
 pub struct FindingContent {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bullets: Option<Vec<String>>,
@@ -136,6 +456,7 @@ pub struct FindingContent {
 
 /// Risk content: early warning or constraint
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct RiskContent {
     pub text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -146,12 +467,14 @@ pub struct RiskContent {
 
 /// Result content: final output (answer, summary, or conclusion)
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ResultOutcome {
     pub text: String,
 }
 
 /// Artifact link content: reference to external artifact
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct ArtifactLinkContent {
     pub label: String,
     pub url: String,
@@ -159,6 +482,7 @@ pub struct ArtifactLinkContent {
 
 /// Result message type definitions
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum ResultMessageType {
     Ack,
@@ -170,6 +494,19 @@ pub enum ResultMessageType {
     ArtifactLink,
 }
 
+impl ResultMessageType {
+    /// Every result message type, in spec order
+    pub const ALL: [ResultMessageType; 7] = [
+        ResultMessageType::Ack,
+        ResultMessageType::ClarifyingQuestion,
+        ResultMessageType::Progress,
+        ResultMessageType::Finding,
+        ResultMessageType::Risk,
+        ResultMessageType::Result,
+        ResultMessageType::ArtifactLink,
+    ];
+}
+
 impl std::fmt::Display for ResultMessageType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let s = match self {
@@ -187,16 +524,22 @@ impl std::fmt::Display for ResultMessageType {
 
 /// Explain why a message was blocked
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct RejectPayload {
     pub message_id: String,
     pub task_id: String,
+    /// Stable reason code, e.g. `message_limit_exceeded`
     pub reason: String,
+    /// Human-readable specifics, sent only by Gateways with verbose rejections
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
 }
 
 /// Agent heartbeat
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct HeartbeatPayload {
-    /// Timestamp
+    /// Unix timestamp (seconds) the heartbeat was sent
     pub ts: u64,
     /// Optional agent description (sent every 3rd heartbeat)
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -208,6 +551,7 @@ pub struct HeartbeatPayload {
 
 /// Mic revoke payload
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MicRevokePayload {
     pub task_id: String,
     pub agent_id: String,
@@ -215,13 +559,316 @@ pub struct MicRevokePayload {
 
 /// Summary payload - conversation summary for context management
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SummaryPayload {
     /// Condensed conversation summary
     pub summary_text: String,
-    /// Timestamp of the latest message included in this summary
+    /// Unix timestamp (seconds) of the latest message included in this summary
     pub covers_until_ts: u64,
     /// Number of messages summarized
     pub message_count: u64,
-    /// When this summary was generated
+    /// Unix timestamp (seconds) this summary was generated
+    pub generated_at: u64,
+}
+
+/// Retained snapshot of a room's active mic grants, for late-joining gateways
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MicGrantSnapshotPayload {
+    pub grants: Vec<MicGrantState>,
+    /// Unix timestamp (seconds) this snapshot was taken
     pub generated_at: u64,
 }
+
+/// Notice that a mic grant ended without being revoked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MicExpiredPayload {
+    pub task_id: String,
+    pub agent_id: String,
+    pub reason: MicExpiryReason,
+}
+
+/// Why a mic grant ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum MicExpiryReason {
+    /// `expires_at` passed
+    Expired,
+    /// `max_messages` were sent
+    Exhausted,
+}
+
+impl std::fmt::Display for MicExpiryReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MicExpiryReason::Expired => write!(f, "expired"),
+            MicExpiryReason::Exhausted => write!(f, "exhausted"),
+        }
+    }
+}
+
+/// Notice to an agent that the Gateway redacted one of its messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RedactionPayload {
+    /// ID of the redacted (and republished) message
+    pub message_id: String,
+    pub task_id: String,
+    pub agent_id: String,
+    pub redactions: Vec<Redaction>,
+}
+
+/// One change made to a result's content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Redaction {
+    /// Policy rule that required the change
+    pub rule: String,
+    /// Content field changed, e.g. `text` or `bullets[2]`
+    pub field: String,
+    pub action: RedactionAction,
+}
+
+/// How a content field was changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionAction {
+    /// Matching text replaced with `[REDACTED:{rule}]`
+    Masked,
+    /// Text cut to the rule's maximum length
+    Truncated,
+}
+
+impl std::fmt::Display for RedactionAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RedactionAction::Masked => write!(f, "masked"),
+            RedactionAction::Truncated => write!(f, "truncated"),
+        }
+    }
+}
+
+/// A valid candidate held by the Gateway until a moderator decides on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ModerationRequestPayload {
+    /// Hold rule that matched, e.g. `risk_severity:high`
+    pub reason: String,
+    /// Unix timestamp (seconds) after which the candidate is rejected undecided
+    pub expires_at: u64,
+    /// The held candidate, exactly as it will be republished if approved
+    pub message: Box<Envelope>,
+}
+
+/// A moderator's decision on a held candidate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ModerationDecisionPayload {
+    /// ID of the held message
+    pub message_id: String,
+    pub decision: ModerationDecision,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Whether a held candidate is published
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationDecision {
+    Approve,
+    Deny,
+}
+
+/// An active mic grant and how much of it has been used
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MicGrantState {
+    #[serde(flatten)]
+    pub grant: MicGrantPayload,
+    /// Messages approved under this grant so far
+    pub messages_sent: u32,
+    /// Messages each pooled gateway approved, keyed by gateway ID
+    ///
+    /// Members counting the same grant each add to their own entry, so
+    /// merging keeps every member's count instead of the highest total.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sent_by: BTreeMap<String, u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_envelope_round_trip() {
+        let json = r#"{
+            "id": "msg_123",
+            "type": "mic_grant",
+            "room_id": "room_1",
+            "from": { "kind": "agent", "id": "facilitator" },
+            "ts": 1734530000,
+            "payload": {
+                "task_id": "task_42",
+                "agent_id": "agent.researcher",
+                "max_messages": 6,
+                "allowed_message_types": ["ack", "result"],
+                "expires_at": 1734531200
+            }
+        }"#;
+
+        let envelope = Envelope::from_slice(json.as_bytes()).unwrap();
+        assert_eq!(envelope.message_type(), EnvelopeType::MicGrant);
+        let Payload::MicGrant(grant) = &envelope.payload else {
+            panic!("expected mic_grant payload");
+        };
+        assert_eq!(grant.max_messages, 6);
+        assert_eq!(envelope.thread_id(), "msg_123");
+
+        let value = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(value["type"], "mic_grant");
+        assert_eq!(value["payload"]["task_id"], "task_42");
+        assert!(value.get("correlation_id").is_none());
+    }
+
+    fn decode_result(
+        message_type: &str,
+        content: serde_json::Value,
+    ) -> Result<ResultPayload, serde_json::Error> {
+        serde_json::from_value(serde_json::json!({
+            "task_id": "task_42",
+            "message_type": message_type,
+            "content": content,
+        }))
+    }
+
+    #[test]
+    fn test_result_content_follows_message_type() {
+        let finding = decode_result("finding", serde_json::json!({ "text": "Option A" })).unwrap();
+        assert!(matches!(finding.content, ResultContent::Finding(_)));
+
+        let result = decode_result("result", serde_json::json!({ "text": "Done" })).unwrap();
+        assert!(matches!(result.content, ResultContent::Result(_)));
+
+        let progress = decode_result("progress", serde_json::json!({ "text": "Working" })).unwrap();
+        assert!(matches!(progress.content, ResultContent::Progress(_)));
+    }
+
+    #[test]
+    fn test_result_redactions_optional() {
+        let plain = decode_result("result", serde_json::json!({ "text": "Done" })).unwrap();
+        assert!(plain.redactions.is_empty());
+        assert!(serde_json::to_value(&plain)
+            .unwrap()
+            .get("redactions")
+            .is_none());
+
+        let redacted: ResultPayload = serde_json::from_value(serde_json::json!({
+            "task_id": "task_42",
+            "message_type": "result",
+            "content": { "text": "Key: [REDACTED:aws-keys]" },
+            "redactions": [{ "rule": "aws-keys", "field": "text", "action": "masked" }]
+        }))
+        .unwrap();
+        assert_eq!(
+            redacted.redactions,
+            vec![Redaction {
+                rule: "aws-keys".to_string(),
+                field: "text".to_string(),
+                action: RedactionAction::Masked,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_result_content_per_type_errors() {
+        let err =
+            decode_result("artifact_link", serde_json::json!({ "text": "nope" })).unwrap_err();
+        assert!(
+            err.to_string().contains("invalid artifact_link content"),
+            "{}",
+            err
+        );
+
+        let err = decode_result("finding", serde_json::json!({})).unwrap_err();
+        assert!(
+            err.to_string().contains("requires `text` or `bullets`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_protocol_version_negotiation() {
+        let legacy = r#"{"id":"m","type":"say","room_id":"r","from":{"kind":"user","id":"u"},"ts":1,"payload":{"text":"hi"}}"#;
+        let envelope = Envelope::from_slice(legacy.as_bytes()).unwrap();
+        assert_eq!(envelope.version, Some(PROTOCOL_VERSION));
+        assert_eq!(envelope.ts, 1000);
+
+        let newer_minor = legacy.replace(r#""id":"m","#, r#""id":"m","version":"0.9","#);
+        let envelope = Envelope::from_slice(newer_minor.as_bytes()).unwrap();
+        assert_eq!(
+            envelope.protocol_version(),
+            ProtocolVersion { major: 0, minor: 9 }
+        );
+
+        let next_major = legacy.replace(r#""id":"m","#, r#""id":"m","version":"1.0","#);
+        let err = Envelope::from_slice(next_major.as_bytes()).unwrap_err();
+        assert!(matches!(err, AorError::UnsupportedVersion(v) if v == "1.0"));
+    }
+
+    #[test]
+    fn test_legacy_envelope_upgraded() {
+        let legacy = serde_json::json!({
+            "id": "msg_1",
+            "type": "result",
+            "room_id": "room_1",
+            "from": { "kind": "agent", "id": "agent.researcher" },
+            "ts": 1734530000,
+            "payload": {
+                "task_id": "task_42",
+                "message_type": "finding",
+                "content": { "bullets": ["Option A is cheaper"] }
+            }
+        });
+
+        let envelope: Envelope = serde_json::from_value(legacy.clone()).unwrap();
+        assert_eq!(envelope.protocol_version(), LEGACY_PROTOCOL_VERSION);
+
+        let upgraded = upgrade(legacy).unwrap();
+        assert_eq!(upgraded["ts"], 1734530000000u64);
+        assert_eq!(upgraded["version"], "0.2");
+
+        // Already upgraded, so republishing it doesn't upgrade it again
+        assert_eq!(upgrade(upgraded.clone()).unwrap(), upgraded);
+
+        let envelope: Envelope = serde_json::from_value(upgraded).unwrap();
+        assert_eq!(envelope.protocol_version(), PROTOCOL_VERSION);
+        assert_eq!(envelope.ts, 1734530000000);
+        let Payload::Result(result) = &envelope.payload else {
+            panic!("expected result payload");
+        };
+        assert!(matches!(result.content, ResultContent::Finding(_)));
+
+        let err = upgrade(serde_json::json!({ "version": 2 })).unwrap_err();
+        assert!(matches!(err, AorError::Schema(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_payload_schema_error_reported() {
+        let json = r#"{
+            "id": "msg_1",
+            "type": "task",
+            "room_id": "room_1",
+            "from": { "kind": "agent", "id": "facilitator" },
+            "ts": 1,
+            "payload": { "goal": "missing task id" }
+        }"#;
+
+        let err = Envelope::from_slice(json.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("task_id"), "{}", err);
+    }
+}
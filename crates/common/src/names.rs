@@ -0,0 +1,285 @@
+//! Validated room and agent identifiers
+//!
+//! Room and agent IDs end up inside MQTT topics (`rooms/{roomId}/agents/{agentId}/...`)
+//! and LLM function names, so both are restricted to a conservative alphabet:
+//! ASCII letters and digits plus `-`, `_` and `.`, starting with a letter or
+//! digit, at most [`MAX_ID_LEN`] characters. This keeps them clear of the MQTT
+//! separator and wildcards (`/`, `+`, `#`) and of whitespace or look-alike
+//! Unicode. Agent IDs must also fit in a tool name once encoded; see
+//! [`MAX_TOOL_NAME_ID_LEN`].
+
+use crate::error::AorError;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// Longest accepted room or agent ID
+pub const MAX_ID_LEN: usize = 64;
+
+/// Longest function name accepted by LLM tool APIs
+pub const MAX_TOOL_NAME_LEN: usize = 64;
+
+/// Longest [`AgentId::to_tool_name`] encoding, leaving room for a tool name
+/// prefix such as `assign_to_`
+pub const MAX_TOOL_NAME_ID_LEN: usize = MAX_TOOL_NAME_LEN - 16;
+
+/// Whether `id` is acceptable as a room or agent ID
+pub fn is_valid_id(id: &str) -> bool {
+    validate_id("ID", id).is_ok()
+}
+
+fn validate_id(kind: &str, id: &str) -> Result<(), AorError> {
+    let invalid = |reason: &str| {
+        Err(AorError::Validation(format!(
+            "{} {:?} {}",
+            kind, id, reason
+        )))
+    };
+
+    if id.is_empty() {
+        return invalid("is empty");
+    }
+    if id.len() > MAX_ID_LEN {
+        return invalid(&format!("is longer than {} characters", MAX_ID_LEN));
+    }
+    if !id.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return invalid("must start with a letter or digit");
+    }
+    if let Some(c) = id
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+    {
+        return invalid(&format!("contains {:?}", c));
+    }
+    Ok(())
+}
+
+/// Require an agent ID's tool name encoding to fit in [`MAX_TOOL_NAME_ID_LEN`]
+fn validate_tool_name(kind: &str, id: &str) -> Result<(), AorError> {
+    let encoded_len: usize = id
+        .chars()
+        .map(|c| if matches!(c, '_' | '.') { 2 } else { 1 })
+        .sum();
+    if encoded_len > MAX_TOOL_NAME_ID_LEN {
+        return Err(AorError::Validation(format!(
+            "{} {:?} is longer than {} characters as a tool name",
+            kind, id, MAX_TOOL_NAME_ID_LEN
+        )));
+    }
+    Ok(())
+}
+
+macro_rules! validated_id {
+    ($(#[$doc:meta])* $name:ident, $kind:literal $(, $check:path)?) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        #[serde(try_from = "String", into = "String")]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn new(id: impl Into<String>) -> Result<Self, AorError> {
+                let id = id.into();
+                validate_id($kind, &id)?;
+                $($check($kind, &id)?;)?
+                Ok(Self(id))
+            }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Borrow<str> for $name {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = AorError;
+
+            fn from_str(s: &str) -> Result<Self, AorError> {
+                Self::new(s)
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = AorError;
+
+            fn try_from(id: String) -> Result<Self, AorError> {
+                Self::new(id)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(id: $name) -> String {
+                id.0
+            }
+        }
+
+        impl From<&$name> for String {
+            fn from(id: &$name) -> String {
+                id.0.clone()
+            }
+        }
+    };
+}
+
+validated_id!(
+    /// Room identifier, safe to embed in topics
+    RoomId,
+    "room ID"
+);
+
+validated_id!(
+    /// Agent identifier, safe to embed in topics and (encoded) tool names
+    AgentId,
+    "agent ID",
+    validate_tool_name
+);
+
+impl AgentId {
+    /// Encode for use in an LLM function name
+    ///
+    /// Function names only allow `[a-zA-Z0-9_-]`, so `_` is written as `__`
+    /// and `.` as `_d`. [`from_tool_name`](Self::from_tool_name) reverses it.
+    /// The result is at most [`MAX_TOOL_NAME_ID_LEN`] characters.
+    pub fn to_tool_name(&self) -> String {
+        let mut encoded = String::with_capacity(self.0.len());
+        for c in self.0.chars() {
+            match c {
+                '_' => encoded.push_str("__"),
+                '.' => encoded.push_str("_d"),
+                c => encoded.push(c),
+            }
+        }
+        encoded
+    }
+
+    /// Decode a name produced by [`to_tool_name`](Self::to_tool_name)
+    pub fn from_tool_name(encoded: &str) -> Result<Self, AorError> {
+        let invalid = || AorError::Validation(format!("invalid agent tool name: {}", encoded));
+
+        let mut decoded = String::with_capacity(encoded.len());
+        let mut chars = encoded.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '_' => match chars.next() {
+                    Some('_') => decoded.push('_'),
+                    Some('d') => decoded.push('.'),
+                    _ => return Err(invalid()),
+                },
+                '.' => return Err(invalid()),
+                c => decoded.push(c),
+            }
+        }
+        Self::new(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_validation() {
+        for valid in ["default", "room_1", "agent.researcher", "math-agent", "A9"] {
+            assert!(RoomId::new(valid).is_ok(), "{}", valid);
+            assert!(AgentId::new(valid).is_ok(), "{}", valid);
+        }
+
+        let too_long = "a".repeat(MAX_ID_LEN + 1);
+        for invalid in [
+            "", "+", "#", "rooms/x", "a/b", "a+b", "a b", ".hidden", "-flag", "café", &too_long,
+        ] {
+            assert!(RoomId::new(invalid).is_err(), "{:?}", invalid);
+            assert!(AgentId::new(invalid).is_err(), "{:?}", invalid);
+        }
+
+        assert!(matches!(
+            AgentId::new("a/b"),
+            Err(AorError::Validation(msg)) if msg.contains("agent ID") && msg.contains("'/'")
+        ));
+    }
+
+    #[test]
+    fn test_agent_id_fits_tool_name() {
+        // Fine as a room ID, but `_` and `.` double in length once encoded
+        let escaped = format!("a{}", "_.".repeat(MAX_TOOL_NAME_ID_LEN / 4));
+        assert!(RoomId::new(&escaped).is_ok());
+        assert!(matches!(
+            AgentId::new(&escaped),
+            Err(AorError::Validation(msg)) if msg.contains("tool name")
+        ));
+
+        let longest = format!("{}_", "a".repeat(MAX_TOOL_NAME_ID_LEN - 2));
+        let agent = AgentId::new(&longest).unwrap();
+        assert_eq!(agent.to_tool_name().len(), MAX_TOOL_NAME_ID_LEN);
+        assert!(AgentId::new("a".repeat(MAX_TOOL_NAME_ID_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_ids_parse_and_deserialize() {
+        let room: RoomId = "ops".parse().unwrap();
+        assert_eq!(room.as_str(), "ops");
+        assert_eq!(&*room, "ops");
+
+        let agent: AgentId = serde_json::from_str(r#""math-agent""#).unwrap();
+        assert_eq!(serde_json::to_string(&agent).unwrap(), r#""math-agent""#);
+        assert!(serde_json::from_str::<AgentId>(r#""a/b""#).is_err());
+    }
+
+    #[test]
+    fn test_tool_name_round_trip() {
+        for id in [
+            "math-agent",
+            "math_agent",
+            "agent.researcher",
+            "a__b",
+            "x_d.y",
+        ] {
+            let agent = AgentId::new(id).unwrap();
+            let encoded = agent.to_tool_name();
+            assert!(
+                encoded
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-')),
+                "{}",
+                encoded
+            );
+            assert_eq!(AgentId::from_tool_name(&encoded).unwrap(), agent);
+        }
+
+        // Hyphens and underscores no longer collide
+        assert_ne!(
+            AgentId::new("math-agent").unwrap().to_tool_name(),
+            AgentId::new("math_agent").unwrap().to_tool_name()
+        );
+
+        for invalid in ["a_", "a_x", "a.b", ""] {
+            assert!(AgentId::from_tool_name(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! Component runtime
+//!
+//! Owns the MQTT plumbing every AOR component used to copy-paste: client
+//! setup, (re)subscribing on connect, periodic heartbeats, reconnect backoff,
+//! ctrl-c shutdown and decoding incoming publishes into [`Envelope`]s.
+//! Components connect with MQTT v5 so handlers can read the user properties a
+//! broker attaches to deliveries, such as the publishing client's identity.
+//!
+//! A component declares who it is with an [`Identity`], lists its topic
+//! filters and handles decoded envelopes by implementing [`Component`]:
+//!
+//! ```ignore
+//! let runtime = Runtime::new(identity, mqtt);
+//! runtime.run(MyComponent::new(config)).await?;
+//! ```
+
+use crate::builder::EnvelopeBuilder;
+use crate::error::AorError;
+use crate::message::{Envelope, HeartbeatPayload, Sender};
+use crate::signing::Signer;
+use crate::time::now_secs;
+use crate::topics::{self, Topic};
+use rumqttc::v5::mqttbytes::v5::{Filter, Packet};
+use rumqttc::v5::mqttbytes::QoS;
+use rumqttc::v5::{AsyncClient, Event, EventLoop, MqttOptions};
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// Number of heartbeats between ones that carry the component description
+const DESCRIPTION_EVERY: u64 = 3;
+
+/// How long to keep polling after ctrl-c so the disconnect reaches the broker
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+
+/// Broker connection settings
+#[derive(Debug, Clone)]
+pub struct MqttSettings {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub keep_alive_secs: u64,
+}
+
+/// Who a component is and how it announces itself
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// Room served, or [`topics::ALL_ROOMS`] to serve every room
+    pub room_id: String,
+    pub sender: Sender,
+    /// Sent on every third heartbeat
+    pub description: String,
+    pub can_accept_tasks: bool,
+    pub heartbeat_interval: Duration,
+    /// Signs every envelope sent as `sender`
+    pub signer: Option<Signer>,
+}
+
+impl Identity {
+    pub fn new(room_id: impl Into<String>, sender: Sender, description: impl Into<String>) -> Self {
+        Self {
+            room_id: room_id.into(),
+            sender,
+            description: description.into(),
+            can_accept_tasks: false,
+            heartbeat_interval: Duration::from_secs(10),
+            signer: None,
+        }
+    }
+
+    pub fn accepting_tasks(mut self) -> Self {
+        self.can_accept_tasks = true;
+        self
+    }
+
+    pub fn heartbeat_every(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    pub fn signed_with(mut self, signer: Signer) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Heartbeat payload for the `beat`-th heartbeat (1-based)
+    fn heartbeat(&self, beat: u64) -> HeartbeatPayload {
+        HeartbeatPayload {
+            ts: now_secs(),
+            description: beat
+                .is_multiple_of(DESCRIPTION_EVERY)
+                .then(|| self.description.clone()),
+            can_accept_tasks: self.can_accept_tasks,
+        }
+    }
+}
+
+/// Handle passed to component callbacks for publishing
+pub struct Context {
+    client: AsyncClient,
+    envelopes: EnvelopeBuilder,
+    room_id: String,
+    sender: Sender,
+    signer: Option<Signer>,
+    /// User properties of the publish being handled
+    user_properties: Vec<(String, String)>,
+}
+
+impl Context {
+    pub fn client(&self) -> &AsyncClient {
+        &self.client
+    }
+
+    /// Builder for envelopes sent as this component into its room
+    ///
+    /// Components serving every room should use [`envelopes_in`](Self::envelopes_in).
+    pub fn envelopes(&self) -> &EnvelopeBuilder {
+        &self.envelopes
+    }
+
+    /// Builder for envelopes sent as this component into `room_id`
+    pub fn envelopes_in(&self, room_id: &str) -> EnvelopeBuilder {
+        EnvelopeBuilder::new(room_id, self.sender.clone())
+    }
+
+    pub fn room_id(&self) -> &str {
+        &self.room_id
+    }
+
+    /// Value of the named MQTT v5 user property on the publish being handled
+    ///
+    /// Only set while handling a publish; always `None` in
+    /// [`on_tick`](Component::on_tick).
+    pub fn user_property(&self, name: &str) -> Option<&str> {
+        self.user_properties
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Serialize and publish an envelope at QoS 1
+    ///
+    /// Envelopes sent as this component are signed first when it has a key;
+    /// others, such as forwarded ones, are published as they are.
+    pub async fn publish(
+        &self,
+        topic: impl Into<String>,
+        envelope: &Envelope,
+    ) -> Result<(), AorError> {
+        self.send(topic.into(), envelope, false).await
+    }
+
+    /// Like [`publish`](Self::publish), but the broker keeps the envelope as
+    /// the topic's retained message for future subscribers
+    pub async fn publish_retained(
+        &self,
+        topic: impl Into<String>,
+        envelope: &Envelope,
+    ) -> Result<(), AorError> {
+        self.send(topic.into(), envelope, true).await
+    }
+
+    async fn send(&self, topic: String, envelope: &Envelope, retain: bool) -> Result<(), AorError> {
+        let payload = match &self.signer {
+            Some(signer) => {
+                let mut envelope = envelope.clone();
+                signer.sign(&mut envelope);
+                serde_json::to_vec(&envelope)
+            }
+            None => serde_json::to_vec(envelope),
+        }
+        .map_err(|e| AorError::Schema(e.to_string()))?;
+        self.client
+            .publish(topic, QoS::AtLeastOnce, retain, payload)
+            .await
+            .map_err(|e| AorError::Mqtt(e.to_string()))
+    }
+}
+
+/// A long-running AOR component driven by [`Runtime`]
+///
+/// Handlers run inline on the event loop, so a handler that awaits for a long
+/// time delays delivery of later messages (but not heartbeats).
+#[allow(async_fn_in_trait)]
+pub trait Component {
+    /// Topic filters to subscribe to, re-applied on every (re)connect
+    fn subscriptions(&self) -> Vec<String>;
+
+    /// Handle an envelope received on `topic`
+    async fn on_envelope(&mut self, ctx: &Context, topic: &str, envelope: Envelope);
+
+    /// Handle a publish that could not be decoded into an envelope
+    async fn on_invalid(&mut self, _ctx: &Context, topic: &str, _payload: &[u8], error: AorError) {
+        warn!("Invalid envelope on {}: {}", topic, error);
+    }
+
+    /// How often to call [`on_tick`](Self::on_tick), if at all
+    fn tick_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Periodic housekeeping, run on the event loop between messages
+    async fn on_tick(&mut self, _ctx: &Context) {}
+}
+
+/// Drives a [`Component`] against an MQTT broker until ctrl-c
+pub struct Runtime {
+    identity: Identity,
+    mqtt: MqttSettings,
+}
+
+impl Runtime {
+    pub fn new(identity: Identity, mqtt: MqttSettings) -> Self {
+        Self { identity, mqtt }
+    }
+
+    pub async fn run<C: Component>(self, mut component: C) -> Result<(), AorError> {
+        let mut options = MqttOptions::new(&self.mqtt.client_id, &self.mqtt.host, self.mqtt.port);
+        options.set_keep_alive(Duration::from_secs(self.mqtt.keep_alive_secs));
+        let (client, mut eventloop) = AsyncClient::new(options, 10);
+
+        let filters: Vec<Filter> = component
+            .subscriptions()
+            .into_iter()
+            .map(|topic| Filter::new(topic, QoS::AtLeastOnce))
+            .collect();
+        for filter in &filters {
+            info!("Subscribing to {}", filter.path);
+        }
+
+        let mut ctx = Context {
+            client: client.clone(),
+            envelopes: EnvelopeBuilder::new(&self.identity.room_id, self.identity.sender.clone()),
+            room_id: self.identity.room_id.clone(),
+            sender: self.identity.sender.clone(),
+            signer: self.identity.signer.clone(),
+            user_properties: Vec::new(),
+        };
+        // Heartbeats go to the served room, or to every room seen so far
+        let serves_all_rooms = self.identity.room_id == topics::ALL_ROOMS;
+        let rooms = Arc::new(Mutex::new(BTreeSet::new()));
+        if !serves_all_rooms {
+            rooms.lock().unwrap().insert(self.identity.room_id.clone());
+        }
+        let heartbeats = tokio::spawn(send_heartbeats(
+            client.clone(),
+            self.identity.clone(),
+            rooms.clone(),
+        ));
+        let mut ticks = component.tick_interval().map(tokio::time::interval);
+        let mut backoff = Backoff::default();
+        let shutdown = tokio::signal::ctrl_c();
+        tokio::pin!(shutdown);
+
+        let result = loop {
+            let event = tokio::select! {
+                event = eventloop.poll() => event,
+                _ = next_tick(&mut ticks) => {
+                    ctx.user_properties.clear();
+                    component.on_tick(&ctx).await;
+                    continue;
+                }
+                _ = &mut shutdown => {
+                    info!("Shutting down");
+                    break Ok(());
+                }
+            };
+
+            match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to {}:{}", self.mqtt.host, self.mqtt.port);
+                    backoff.reset();
+                    // Subscribing awaits channel capacity, which only the event
+                    // loop frees up, so it must not block this task.
+                    if !filters.is_empty() {
+                        let client = client.clone();
+                        let filters = filters.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = client.subscribe_many(filters).await {
+                                error!("Failed to subscribe: {}", e);
+                            }
+                        });
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(p))) => {
+                    let topic_name = String::from_utf8_lossy(&p.topic);
+                    let topic = Topic::parse(&topic_name).ok();
+                    if let Some(room_id) = topic.map(|t| t.room_id()).filter(|_| serves_all_rooms) {
+                        let mut rooms = rooms.lock().unwrap();
+                        if !rooms.contains(room_id) {
+                            info!("Serving room {}", room_id);
+                            rooms.insert(room_id.to_string());
+                        }
+                    }
+                    ctx.user_properties = p
+                        .properties
+                        .as_ref()
+                        .map(|properties| properties.user_properties.clone())
+                        .unwrap_or_default();
+                    match Envelope::from_slice(&p.payload) {
+                        Ok(envelope) => component.on_envelope(&ctx, &topic_name, envelope).await,
+                        Err(e) => component.on_invalid(&ctx, &topic_name, &p.payload, e).await,
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let delay = backoff.next_delay();
+                    error!("MQTT error: {} (retrying in {:?})", e, delay);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        };
+
+        heartbeats.abort();
+        disconnect(&client, &mut eventloop).await;
+        result
+    }
+}
+
+async fn send_heartbeats(
+    client: AsyncClient,
+    identity: Identity,
+    rooms: Arc<Mutex<BTreeSet<String>>>,
+) {
+    let mut interval = tokio::time::interval(identity.heartbeat_interval);
+    let mut beat = 0u64;
+
+    loop {
+        interval.tick().await;
+        beat += 1;
+
+        let rooms: Vec<String> = rooms.lock().unwrap().iter().cloned().collect();
+        for room_id in rooms {
+            let envelopes = EnvelopeBuilder::new(&room_id, identity.sender.clone());
+            let mut heartbeat = envelopes.heartbeat(identity.heartbeat(beat));
+            if let Some(signer) = &identity.signer {
+                signer.sign(&mut heartbeat);
+            }
+            let topic = topics::agent_heartbeat(&room_id, &identity.sender.id);
+            let payload = serde_json::to_vec(&heartbeat).unwrap();
+            if let Err(e) = client
+                .publish(topic, QoS::AtLeastOnce, false, payload)
+                .await
+            {
+                error!("Failed to send heartbeat to {}: {}", room_id, e);
+            } else {
+                debug!("Sent heartbeat #{} to {}", beat, room_id);
+            }
+        }
+    }
+}
+
+/// Wait for the next component tick, or forever if it has none
+async fn next_tick(ticks: &mut Option<tokio::time::Interval>) {
+    match ticks {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Send DISCONNECT and poll until the broker connection closes or times out
+async fn disconnect(client: &AsyncClient, eventloop: &mut EventLoop) {
+    if client.try_disconnect().is_err() {
+        return;
+    }
+    let _ = tokio::time::timeout(SHUTDOWN_GRACE, async {
+        while eventloop.poll().await.is_ok() {}
+    })
+    .await;
+}
+
+/// Exponential reconnect delay, reset once a connection succeeds
+#[derive(Debug)]
+struct Backoff {
+    current: Duration,
+}
+
+impl Backoff {
+    const INITIAL: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(30);
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(Self::MAX);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.current = Self::INITIAL;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            current: Self::INITIAL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_caps_and_resets() {
+        let mut backoff = Backoff::default();
+        let delays: Vec<u64> = (0..7).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_heartbeat_description_every_third_beat() {
+        let identity =
+            Identity::new("room_1", Sender::agent("agent"), "Does things").accepting_tasks();
+
+        let described: Vec<bool> = (1..=6)
+            .map(|beat| identity.heartbeat(beat).description.is_some())
+            .collect();
+        assert_eq!(described, vec![false, false, true, false, false, true]);
+        assert!(identity.heartbeat(1).can_accept_tasks);
+    }
+}
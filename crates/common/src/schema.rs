@@ -0,0 +1,292 @@
+//! JSON Schema documents for the AOR protocol
+//!
+//! Schemas are generated from the `common::message` types, so they track the
+//! Rust definitions. Documents are keyed by name:
+//!
+//! - `envelope` - a complete envelope, any type
+//! - `say`, `task`, `mic_grant`, ... - the `payload` of each envelope type
+//! - `result_content.ack`, `result_content.finding`, ... - the result
+//!   `content` for each `message_type`
+//!
+//! The generated documents are also checked in under `schemas/` for clients
+//! that don't build this crate.
+
+use crate::error::AorError;
+use crate::message::{
+    AckContent, ArtifactLinkContent, ClarifyingQuestionContent, Envelope, FindingContent,
+    HeartbeatPayload, MicExpiredPayload, MicGrantPayload, MicGrantSnapshotPayload,
+    MicRevokePayload, ModerationDecisionPayload, ModerationRequestPayload, ProgressContent,
+    ProtocolVersion, Redaction, RedactionPayload, RejectPayload, ResultMessageType, ResultOutcome,
+    ResultPayload, RiskContent, SayPayload, SummaryPayload, TaskPayload,
+};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+/// Name of the complete envelope schema document
+pub const ENVELOPE: &str = "envelope";
+
+/// All schema documents, keyed by name
+pub fn schemas() -> BTreeMap<String, Value> {
+    let mut schemas = BTreeMap::new();
+    schemas.insert(ENVELOPE.to_string(), root::<Envelope>());
+    schemas.insert("say".to_string(), root::<SayPayload>());
+    schemas.insert("task".to_string(), root::<TaskPayload>());
+    schemas.insert("mic_grant".to_string(), root::<MicGrantPayload>());
+    schemas.insert("mic_revoke".to_string(), root::<MicRevokePayload>());
+    schemas.insert("result".to_string(), root::<ResultPayload>());
+    schemas.insert("reject".to_string(), root::<RejectPayload>());
+    schemas.insert("heartbeat".to_string(), root::<HeartbeatPayload>());
+    schemas.insert("summary".to_string(), root::<SummaryPayload>());
+    schemas.insert(
+        "mic_grant_snapshot".to_string(),
+        root::<MicGrantSnapshotPayload>(),
+    );
+    schemas.insert("mic_expired".to_string(), root::<MicExpiredPayload>());
+    schemas.insert("redaction".to_string(), root::<RedactionPayload>());
+    schemas.insert(
+        "moderation_request".to_string(),
+        root::<ModerationRequestPayload>(),
+    );
+    schemas.insert(
+        "moderation_decision".to_string(),
+        root::<ModerationDecisionPayload>(),
+    );
+
+    for message_type in ResultMessageType::ALL {
+        let schema = match message_type {
+            ResultMessageType::Ack => root::<AckContent>(),
+            ResultMessageType::ClarifyingQuestion => root::<ClarifyingQuestionContent>(),
+            ResultMessageType::Progress => root::<ProgressContent>(),
+            ResultMessageType::Finding => root::<FindingContent>(),
+            ResultMessageType::Risk => root::<RiskContent>(),
+            ResultMessageType::Result => root::<ResultOutcome>(),
+            ResultMessageType::ArtifactLink => root::<ArtifactLinkContent>(),
+        };
+        schemas.insert(format!("result_content.{}", message_type), schema);
+    }
+
+    schemas
+}
+
+/// Look up a single schema document by name
+pub fn schema(name: &str) -> Option<Value> {
+    schemas().remove(name)
+}
+
+/// Validate arbitrary JSON against the named schema document
+///
+/// All violations are reported in one `AorError::Schema`, each prefixed with
+/// the JSON pointer of the offending value.
+pub fn validate(name: &str, instance: &Value) -> Result<(), AorError> {
+    let validator = validators()
+        .get(name)
+        .ok_or_else(|| AorError::Schema(format!("unknown schema: {}", name)))?;
+
+    let errors: Vec<String> = validator
+        .iter_errors(instance)
+        .map(|e| format!("{}: {}", e.instance_path, e))
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AorError::Schema(errors.join("; ")))
+    }
+}
+
+/// Validate arbitrary JSON against the envelope schema
+pub fn validate_envelope(instance: &Value) -> Result<(), AorError> {
+    validate(ENVELOPE, instance)
+}
+
+fn validators() -> &'static BTreeMap<String, jsonschema::Validator> {
+    static VALIDATORS: OnceLock<BTreeMap<String, jsonschema::Validator>> = OnceLock::new();
+    VALIDATORS.get_or_init(|| {
+        schemas()
+            .into_iter()
+            .map(|(name, schema)| {
+                let validator = jsonschema::validator_for(&schema)
+                    .unwrap_or_else(|e| panic!("generated schema {} is invalid: {}", name, e));
+                (name, validator)
+            })
+            .collect()
+    })
+}
+
+fn root<T: JsonSchema>() -> Value {
+    SchemaGenerator::default()
+        .into_root_schema_for::<T>()
+        .to_value()
+}
+
+impl JsonSchema for ProtocolVersion {
+    fn schema_name() -> Cow<'static, str> {
+        "ProtocolVersion".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "Protocol version as `major.minor`",
+            "type": "string",
+            "pattern": r"^\d+\.\d+$"
+        })
+    }
+}
+
+/// `content` is constrained by `message_type`, mirroring `ResultContent::decode`
+impl JsonSchema for ResultPayload {
+    fn schema_name() -> Cow<'static, str> {
+        "ResultPayload".into()
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let variants: Vec<Value> = ResultMessageType::ALL
+            .iter()
+            .map(|message_type| {
+                let content = match message_type {
+                    ResultMessageType::Ack => generator.subschema_for::<AckContent>(),
+                    ResultMessageType::ClarifyingQuestion => {
+                        generator.subschema_for::<ClarifyingQuestionContent>()
+                    }
+                    ResultMessageType::Progress => generator.subschema_for::<ProgressContent>(),
+                    ResultMessageType::Finding => generator.subschema_for::<FindingContent>(),
+                    ResultMessageType::Risk => generator.subschema_for::<RiskContent>(),
+                    ResultMessageType::Result => generator.subschema_for::<ResultOutcome>(),
+                    ResultMessageType::ArtifactLink => {
+                        generator.subschema_for::<ArtifactLinkContent>()
+                    }
+                };
+                serde_json::json!({
+                    "properties": {
+                        "message_type": { "const": message_type.to_string() },
+                        "content": content
+                    }
+                })
+            })
+            .collect();
+
+        json_schema!({
+            "description": "Structured agent disclosure",
+            "type": "object",
+            "properties": {
+                "task_id": { "type": "string" },
+                "message_type": generator.subschema_for::<ResultMessageType>(),
+                "content": {},
+                "redactions": generator.subschema_for::<Vec<Redaction>>()
+            },
+            "required": ["task_id", "message_type", "content"],
+            "oneOf": variants
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SCHEMA_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../schemas");
+
+    /// Fill in the envelope fields SPEC.md examples leave out
+    fn complete_envelope(example: Value) -> Option<Value> {
+        // §6 examples show only a result's `message_type` and `content`
+        let example = if example.get("message_type").is_some() {
+            let mut payload = example;
+            payload["task_id"] = json!("task_42");
+            json!({ "type": "result", "payload": payload })
+        } else {
+            example
+        };
+
+        // The envelope template in §4 lists alternatives rather than values
+        if example["type"].as_str()?.contains('|') {
+            return None;
+        }
+
+        let mut envelope = json!({
+            "id": "msg_123",
+            "room_id": "room_1",
+            "from": { "kind": "agent", "id": "agent.researcher" },
+            "ts": 1734530000
+        });
+        envelope
+            .as_object_mut()?
+            .extend(example.as_object()?.clone());
+        Some(envelope)
+    }
+
+    #[test]
+    fn test_spec_examples_validate() {
+        let spec = include_str!("../../../SPEC.md");
+        let examples: Vec<Value> = spec
+            .split("```json")
+            .skip(1)
+            .filter_map(|block| block.split("```").next())
+            .map(|block| serde_json::from_str(block).expect("SPEC.md example is not JSON"))
+            .collect();
+
+        let mut validated = 0;
+        for example in examples {
+            let Some(envelope) = complete_envelope(example) else {
+                continue;
+            };
+            validate_envelope(&envelope).unwrap_or_else(|e| panic!("{}\n{:#}", e, envelope));
+            Envelope::from_slice(envelope.to_string().as_bytes())
+                .unwrap_or_else(|e| panic!("{}\n{:#}", e, envelope));
+            validated += 1;
+        }
+        assert!(validated >= 15, "only {} examples validated", validated);
+    }
+
+    #[test]
+    fn test_validation_errors_follow_message_type() {
+        let envelope = json!({
+            "id": "msg_1",
+            "type": "result",
+            "room_id": "room_1",
+            "from": { "kind": "agent", "id": "agent" },
+            "ts": 1,
+            "payload": {
+                "task_id": "task_1",
+                "message_type": "finding",
+                "content": { "severity": "high" }
+            }
+        });
+        assert!(validate_envelope(&envelope).is_err());
+
+        assert!(validate("result_content.finding", &json!({ "bullets": ["a"] })).is_ok());
+        assert!(validate("result_content.artifact_link", &json!({ "label": "x" })).is_err());
+        assert!(validate("say", &json!({ "text": "hi" })).is_ok());
+        assert!(matches!(
+            validate("nope", &json!({})),
+            Err(AorError::Schema(msg)) if msg.contains("unknown schema")
+        ));
+    }
+
+    /// Regenerate with `AOR_UPDATE_SCHEMAS=1 cargo test -p common --features schema`
+    #[test]
+    fn test_checked_in_schemas_up_to_date() {
+        let update = std::env::var_os("AOR_UPDATE_SCHEMAS").is_some();
+        if update {
+            std::fs::create_dir_all(SCHEMA_DIR).unwrap();
+        }
+
+        for (name, schema) in schemas() {
+            let path = format!("{}/{}.schema.json", SCHEMA_DIR, name);
+            let generated = serde_json::to_string_pretty(&schema).unwrap() + "\n";
+            if update {
+                std::fs::write(&path, generated).unwrap();
+            } else {
+                let checked_in = std::fs::read_to_string(&path).unwrap_or_default();
+                assert!(
+                    checked_in == generated,
+                    "{} is out of date; rerun with AOR_UPDATE_SCHEMAS=1",
+                    path
+                );
+            }
+        }
+    }
+}
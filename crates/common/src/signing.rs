@@ -0,0 +1,317 @@
+//! Detached Ed25519 envelope signatures
+//!
+//! Broker ACLs stop clients publishing where they shouldn't, but a
+//! compromised broker can still forge any envelope. Components holding a key
+//! sign the envelopes they send as themselves; receivers check them against a
+//! registry of public keys keyed by sender ID.
+//!
+//! The signature covers the envelope's canonical JSON: every field except
+//! `signature`, object keys sorted, no whitespace, and members whose value is
+//! `null` left out. Receivers rebuild it from the decoded envelope, which
+//! drops unset optional fields, so a sender writing `"format": null` must sign
+//! as if the field were absent. Fields receivers don't know about (from a
+//! newer minor protocol version) make a signature fail rather than pass
+//! unchecked.
+//!
+//! Keys are 32 random bytes in base64, e.g. from `openssl rand -base64 32`.
+//! The registry is a JSON object of base64 public keys:
+//!
+//! ```json
+//! { "facilitator": "5Xn0...", "gateway": "Gq4p...", "math-agent": "oO7T..." }
+//! ```
+
+use crate::error::AorError;
+use crate::message::Envelope;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer as _, SigningKey, VerifyingKey};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Signs envelopes sent as one sender
+#[derive(Clone)]
+pub struct Signer {
+    sender_id: String,
+    key: SigningKey,
+}
+
+impl Signer {
+    pub fn new(sender_id: impl Into<String>, secret: [u8; 32]) -> Self {
+        Self {
+            sender_id: sender_id.into(),
+            key: SigningKey::from_bytes(&secret),
+        }
+    }
+
+    /// Load the secret key for `sender_id` from a base64 key file
+    pub fn load(sender_id: impl Into<String>, path: impl AsRef<Path>) -> Result<Self, AorError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            AorError::Signature(format!("failed to read key {}: {}", path.display(), e))
+        })?;
+        let secret = decode_key(text.trim())
+            .ok_or_else(|| AorError::Signature(format!("invalid key {}", path.display())))?;
+        Ok(Self::new(sender_id, secret))
+    }
+
+    pub fn sender_id(&self) -> &str {
+        &self.sender_id
+    }
+
+    /// Base64 public key, for the receivers' key registry
+    pub fn public_key(&self) -> String {
+        BASE64.encode(self.key.verifying_key().to_bytes())
+    }
+
+    /// Sign `envelope` if it is sent as this signer
+    ///
+    /// Envelopes from other senders, e.g. ones being forwarded, keep their
+    /// own signature.
+    pub fn sign(&self, envelope: &mut Envelope) {
+        if envelope.from.id != self.sender_id {
+            return;
+        }
+        let signature = self.key.sign(&signed_bytes(envelope));
+        envelope.signature = Some(BASE64.encode(signature.to_bytes()));
+    }
+}
+
+impl std::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Signer")
+            .field("sender_id", &self.sender_id)
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+/// Why an envelope's signature was not accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    Unsigned,
+    UnknownSigner(String),
+    Malformed,
+    Invalid,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::Unsigned => f.write_str("envelope is not signed"),
+            SignatureError::UnknownSigner(id) => write!(f, "no key registered for {}", id),
+            SignatureError::Malformed => f.write_str("signature is not a base64 Ed25519 signature"),
+            SignatureError::Invalid => f.write_str("signature does not match"),
+        }
+    }
+}
+
+/// Public keys of known senders
+#[derive(Debug, Default)]
+pub struct KeyRegistry {
+    keys: HashMap<String, VerifyingKey>,
+}
+
+impl KeyRegistry {
+    /// Load a JSON object mapping sender IDs to base64 public keys
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AorError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| {
+            AorError::Signature(format!(
+                "failed to read key registry {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let entries: HashMap<String, String> = serde_json::from_slice(&bytes).map_err(|e| {
+            AorError::Signature(format!("invalid key registry {}: {}", path.display(), e))
+        })?;
+
+        let mut registry = Self::default();
+        for (sender_id, public_key) in entries {
+            let key = decode_key(&public_key)
+                .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+                .ok_or_else(|| {
+                    AorError::Signature(format!("invalid public key for {}", sender_id))
+                })?;
+            registry.keys.insert(sender_id, key);
+        }
+        Ok(registry)
+    }
+
+    /// Register a sender's public key
+    pub fn insert(&mut self, sender_id: impl Into<String>, signer: &Signer) {
+        self.keys
+            .insert(sender_id.into(), signer.key.verifying_key());
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Check that `envelope` is signed by the key registered for `from.id`
+    pub fn verify(&self, envelope: &Envelope) -> Result<(), SignatureError> {
+        let encoded = envelope
+            .signature
+            .as_deref()
+            .ok_or(SignatureError::Unsigned)?;
+        let key = self
+            .keys
+            .get(&envelope.from.id)
+            .ok_or_else(|| SignatureError::UnknownSigner(envelope.from.id.clone()))?;
+        let signature = BASE64
+            .decode(encoded)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or(SignatureError::Malformed)?;
+        key.verify_strict(&signed_bytes(envelope), &signature)
+            .map_err(|_| SignatureError::Invalid)
+    }
+}
+
+fn decode_key(encoded: &str) -> Option<[u8; 32]> {
+    BASE64.decode(encoded).ok()?.try_into().ok()
+}
+
+/// Canonical JSON of everything in `envelope` but its signature
+fn signed_bytes(envelope: &Envelope) -> Vec<u8> {
+    let mut value = serde_json::to_value(envelope).expect("envelopes serialize");
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("signature");
+    }
+    let mut out = String::new();
+    write_canonical(&value, &mut out);
+    out.into_bytes()
+}
+
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            let mut keys: Vec<&String> = fields
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, _)| key)
+                .collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(&fields[key], out);
+            }
+            out.push('}');
+        }
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::EnvelopeBuilder;
+    use crate::message::{Payload, SayPayload, Sender};
+
+    fn facilitator() -> Signer {
+        Signer::new("facilitator", [7; 32])
+    }
+
+    fn registry() -> KeyRegistry {
+        let mut registry = KeyRegistry::default();
+        registry.insert("facilitator", &facilitator());
+        registry
+    }
+
+    #[test]
+    fn test_signed_envelope_verifies_after_round_trip() {
+        let mut envelope = EnvelopeBuilder::new("room_1", Sender::system("facilitator")).say("hi");
+        facilitator().sign(&mut envelope);
+
+        let bytes = serde_json::to_vec(&envelope).unwrap();
+        let decoded = Envelope::from_slice(&bytes).unwrap();
+        assert_eq!(registry().verify(&decoded), Ok(()));
+    }
+
+    #[test]
+    fn test_tampered_or_foreign_envelopes_fail() {
+        let builder = EnvelopeBuilder::new("room_1", Sender::system("facilitator"));
+        let mut envelope = builder.say("hi");
+        facilitator().sign(&mut envelope);
+
+        let mut tampered = envelope.clone();
+        tampered.payload = Payload::Say(SayPayload {
+            text: "bye".to_string(),
+        });
+        assert_eq!(registry().verify(&tampered), Err(SignatureError::Invalid));
+
+        let mut forged = envelope.clone();
+        Signer::new("facilitator", [8; 32]).sign(&mut forged);
+        assert_eq!(registry().verify(&forged), Err(SignatureError::Invalid));
+
+        assert_eq!(
+            registry().verify(&builder.say("unsigned")),
+            Err(SignatureError::Unsigned)
+        );
+
+        let mut agent = EnvelopeBuilder::new("room_1", Sender::agent("agent")).say("hi");
+        Signer::new("agent", [9; 32]).sign(&mut agent);
+        assert_eq!(
+            registry().verify(&agent),
+            Err(SignatureError::UnknownSigner("agent".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_canonical_json_omits_nulls() {
+        let mut out = String::new();
+        write_canonical(
+            &serde_json::json!({ "b": null, "a": { "y": [1, null], "x": null } }),
+            &mut out,
+        );
+        assert_eq!(out, r#"{"a":{"y":[1,null]}}"#);
+    }
+
+    #[test]
+    fn test_raw_json_with_nulls_verifies() {
+        // As a non-Rust sender would write it, with unset optionals as null
+        let mut raw = serde_json::json!({
+            "id": "01JF0000000000000000000000",
+            "version": "0.2",
+            "room_id": "room_1",
+            "from": { "kind": "agent", "id": "facilitator" },
+            "ts": 1734530600000u64,
+            "correlation_id": null,
+            "type": "task",
+            "payload": { "task_id": "task_1", "goal": "Add 2 and 2", "format": null }
+        });
+        let mut canonical = String::new();
+        write_canonical(&raw, &mut canonical);
+        let signature = facilitator().key.sign(canonical.as_bytes());
+        raw["signature"] = BASE64.encode(signature.to_bytes()).into();
+
+        let envelope = Envelope::from_slice(raw.to_string().as_bytes()).unwrap();
+        assert_eq!(registry().verify(&envelope), Ok(()));
+    }
+
+    #[test]
+    fn test_signer_leaves_other_senders_alone() {
+        let mut envelope = EnvelopeBuilder::new("room_1", Sender::agent("agent")).say("hi");
+        facilitator().sign(&mut envelope);
+        assert!(envelope.signature.is_none());
+    }
+}
@@ -0,0 +1,16 @@
+//! Clock helpers shared across AOR components
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current Unix time in milliseconds (the envelope `ts` resolution per spec)
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Current Unix time in seconds, as used by grant expiries and heartbeats
+pub fn now_secs() -> u64 {
+    now_millis() / 1000
+}
@@ -1,4 +1,22 @@
 //! Topic naming utilities per AOR spec
+//!
+//! The functions below build topic strings; [`Topic`] parses them back.
+
+use crate::error::AorError;
+use crate::names::is_valid_id;
+use std::fmt;
+
+/// Room ID that matches every room when used in a subscription (`rooms/+/...`)
+pub const ALL_ROOMS: &str = "+";
+
+/// Room to subscribe to: `room_id`, or every room when `all_rooms` is set
+pub fn room_filter(room_id: &str, all_rooms: bool) -> &str {
+    if all_rooms {
+        ALL_ROOMS
+    } else {
+        room_id
+    }
+}
 
 /// Public chat (approved messages only)
 pub fn public(room_id: &str) -> String {
@@ -40,6 +58,149 @@ pub fn summary(room_id: &str) -> String {
     format!("rooms/{}/summary", room_id)
 }
 
+/// Retained snapshot of active mic grants
+pub fn mic_grants(room_id: &str) -> String {
+    format!("rooms/{}/mic_grants", room_id)
+}
+
+/// Held candidates awaiting a human moderator, and moderator decisions
+pub fn moderation(room_id: &str) -> String {
+    format!("rooms/{}/moderation", room_id)
+}
+
+/// Candidates forwarded to the pooled gateway that owns their mic grant
+pub fn gateway_candidates(room_id: &str, gateway_id: &str) -> String {
+    format!("rooms/{}/gateways/{}/candidates", room_id, gateway_id)
+}
+
+/// Shared subscription: each message matching `filter` goes to one member of `group`
+pub fn shared(group: &str, filter: &str) -> String {
+    format!("$share/{}/{}", group, filter)
+}
+
+/// A concrete `rooms/{roomId}/...` topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topic<'a> {
+    Public {
+        room_id: &'a str,
+    },
+    PublicCandidates {
+        room_id: &'a str,
+    },
+    Control {
+        room_id: &'a str,
+    },
+    Inbox {
+        room_id: &'a str,
+        agent_id: &'a str,
+    },
+    Work {
+        room_id: &'a str,
+        agent_id: &'a str,
+    },
+    Heartbeat {
+        room_id: &'a str,
+        agent_id: &'a str,
+    },
+    Summary {
+        room_id: &'a str,
+    },
+    MicGrants {
+        room_id: &'a str,
+    },
+    Moderation {
+        room_id: &'a str,
+    },
+    GatewayCandidates {
+        room_id: &'a str,
+        gateway_id: &'a str,
+    },
+}
+
+impl<'a> Topic<'a> {
+    /// Parse a topic received from the broker
+    ///
+    /// Wildcard filters are rejected, as are room and agent IDs that
+    /// [`is_valid_id`](crate::names::is_valid_id) refuses.
+    pub fn parse(topic: &'a str) -> Result<Self, AorError> {
+        let invalid = || AorError::Validation(format!("invalid topic: {}", topic));
+        let parts: Vec<&str> = topic.split('/').collect();
+
+        let parsed = match parts.as_slice() {
+            ["rooms", room_id, "public"] => Topic::Public { room_id },
+            ["rooms", room_id, "public_candidates"] => Topic::PublicCandidates { room_id },
+            ["rooms", room_id, "control"] => Topic::Control { room_id },
+            ["rooms", room_id, "summary"] => Topic::Summary { room_id },
+            ["rooms", room_id, "mic_grants"] => Topic::MicGrants { room_id },
+            ["rooms", room_id, "moderation"] => Topic::Moderation { room_id },
+            ["rooms", room_id, "agents", agent_id, "inbox"] => Topic::Inbox { room_id, agent_id },
+            ["rooms", room_id, "agents", agent_id, "work"] => Topic::Work { room_id, agent_id },
+            ["rooms", room_id, "agents", agent_id, "heartbeat"] => {
+                Topic::Heartbeat { room_id, agent_id }
+            }
+            ["rooms", room_id, "gateways", gateway_id, "candidates"] => Topic::GatewayCandidates {
+                room_id,
+                gateway_id,
+            },
+            _ => return Err(invalid()),
+        };
+
+        let ids_valid = is_valid_id(parsed.room_id()) && parsed.agent_id().is_none_or(is_valid_id);
+        if ids_valid {
+            Ok(parsed)
+        } else {
+            Err(invalid())
+        }
+    }
+
+    pub fn room_id(&self) -> &'a str {
+        match *self {
+            Topic::Public { room_id }
+            | Topic::PublicCandidates { room_id }
+            | Topic::Control { room_id }
+            | Topic::Summary { room_id }
+            | Topic::MicGrants { room_id }
+            | Topic::Moderation { room_id }
+            | Topic::Inbox { room_id, .. }
+            | Topic::Work { room_id, .. }
+            | Topic::Heartbeat { room_id, .. }
+            | Topic::GatewayCandidates { room_id, .. } => room_id,
+        }
+    }
+
+    /// Agent (or gateway) ID for per-agent topics
+    pub fn agent_id(&self) -> Option<&'a str> {
+        match *self {
+            Topic::Inbox { agent_id, .. }
+            | Topic::Work { agent_id, .. }
+            | Topic::Heartbeat { agent_id, .. } => Some(agent_id),
+            Topic::GatewayCandidates { gateway_id, .. } => Some(gateway_id),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Topic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let topic = match *self {
+            Topic::Public { room_id } => public(room_id),
+            Topic::PublicCandidates { room_id } => public_candidates(room_id),
+            Topic::Control { room_id } => control(room_id),
+            Topic::Summary { room_id } => summary(room_id),
+            Topic::MicGrants { room_id } => mic_grants(room_id),
+            Topic::Moderation { room_id } => moderation(room_id),
+            Topic::Inbox { room_id, agent_id } => agent_inbox(room_id, agent_id),
+            Topic::Work { room_id, agent_id } => agent_work(room_id, agent_id),
+            Topic::Heartbeat { room_id, agent_id } => agent_heartbeat(room_id, agent_id),
+            Topic::GatewayCandidates {
+                room_id,
+                gateway_id,
+            } => gateway_candidates(room_id, gateway_id),
+        };
+        f.write_str(&topic)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -58,4 +219,70 @@ mod tests {
             "rooms/test/agents/researcher/work"
         );
     }
+
+    #[test]
+    fn test_all_rooms_filter() {
+        let room = room_filter("test", true);
+        assert_eq!(public(room), "rooms/+/public");
+        assert_eq!(all_agent_heartbeats(room), "rooms/+/agents/+/heartbeat");
+        assert_eq!(room_filter("test", false), "test");
+
+        assert!(Topic::parse(&public(room)).is_err());
+        assert!(Topic::parse(&all_agent_heartbeats("ops")).is_err());
+        assert_eq!(
+            shared("gateways", &public_candidates(room)),
+            "$share/gateways/rooms/+/public_candidates"
+        );
+    }
+
+    #[test]
+    fn test_topic_parsing() {
+        let topics = [
+            Topic::Public { room_id: "ops" },
+            Topic::PublicCandidates { room_id: "ops" },
+            Topic::Control { room_id: "ops" },
+            Topic::Summary { room_id: "ops" },
+            Topic::MicGrants { room_id: "ops" },
+            Topic::Moderation { room_id: "ops" },
+            Topic::GatewayCandidates {
+                room_id: "ops",
+                gateway_id: "gateway-b",
+            },
+            Topic::Inbox {
+                room_id: "ops",
+                agent_id: "agent.researcher",
+            },
+            Topic::Work {
+                room_id: "ops",
+                agent_id: "math-agent",
+            },
+            Topic::Heartbeat {
+                room_id: "room_1",
+                agent_id: "facilitator",
+            },
+        ];
+        for topic in topics {
+            let formatted = topic.to_string();
+            assert_eq!(Topic::parse(&formatted).unwrap(), topic, "{}", formatted);
+        }
+
+        let heartbeat = Topic::parse("rooms/ops/agents/a/heartbeat").unwrap();
+        assert_eq!(heartbeat.room_id(), "ops");
+        assert_eq!(heartbeat.agent_id(), Some("a"));
+        assert_eq!(Topic::parse("rooms/ops/public").unwrap().agent_id(), None);
+
+        for invalid in [
+            "other/ops/public",
+            "rooms/ops",
+            "rooms/ops/unknown",
+            "rooms/ops/public/extra",
+            "rooms//public",
+            "rooms/ops room/public",
+            "rooms/#/public",
+            "rooms/ops/agents//inbox",
+            "rooms/ops/agents/a+b/inbox",
+        ] {
+            assert!(Topic::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
 }
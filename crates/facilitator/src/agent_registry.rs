@@ -1,7 +1,8 @@
 //! Tracks available agents via heartbeats
 
+use common::time::now_secs;
+use common::AgentId;
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, info};
 
 #[derive(Debug, Clone)]
@@ -12,7 +13,7 @@ pub struct AgentInfo {
 
 #[derive(Debug)]
 pub struct AgentRegistry {
-    agents: HashMap<String, AgentInfo>,
+    agents: HashMap<AgentId, AgentInfo>,
     timeout_secs: u64,
 }
 
@@ -24,11 +25,8 @@ impl AgentRegistry {
         }
     }
 
-    pub fn update_agent(&mut self, agent_id: String, description: Option<String>) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    pub fn update_agent(&mut self, agent_id: AgentId, description: Option<String>) {
+        let now = now_secs();
 
         let _is_new = !self.agents.contains_key(&agent_id);
 
@@ -50,11 +48,8 @@ impl AgentRegistry {
         }
     }
 
-    pub fn get_active_agents(&self) -> Vec<String> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    pub fn get_active_agents(&self) -> Vec<AgentId> {
+        let now = now_secs();
 
         self.agents
             .iter()
@@ -63,15 +58,8 @@ impl AgentRegistry {
             .collect()
     }
 
-    pub fn get_agent_info(&self, agent_id: &str) -> Option<&AgentInfo> {
-        self.agents.get(agent_id)
-    }
-
-    pub fn get_active_agents_with_descriptions(&self) -> Vec<(String, Option<String>)> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+    pub fn get_active_agents_with_descriptions(&self) -> Vec<(AgentId, Option<String>)> {
+        let now = now_secs();
 
         self.agents
             .iter()
@@ -1,6 +1,7 @@
 //! Facilitator configuration
 
 use clap::Parser;
+use common::RoomId;
 
 #[derive(Debug, Parser)]
 #[command(name = "facilitator")]
@@ -45,7 +46,11 @@ pub struct FacilitatorConfig {
 
     /// Room ID
     #[arg(long, env = "AOR_ROOM_ID", default_value = "default")]
-    pub room_id: String,
+    pub room_id: RoomId,
+
+    /// Serve every room via `rooms/+/...` subscriptions instead of only --room-id
+    #[arg(long, env = "AOR_ALL_ROOMS", default_value = "false")]
+    pub all_rooms: bool,
 
     /// Default mic duration in seconds
     #[arg(
@@ -62,4 +67,8 @@ pub struct FacilitatorConfig {
         default_value = "10"
     )]
     pub default_max_messages: u32,
+
+    /// Base64 Ed25519 secret key file used to sign envelopes sent by this facilitator
+    #[arg(long, env = "AOR_SIGNING_KEY")]
+    pub signing_key: Option<String>,
 }
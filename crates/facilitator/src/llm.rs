@@ -1,43 +1,31 @@
 //! LLM-based intent interpretation
 
-use common::{ChatMessage, ChatRequest, FunctionDefinition, LlmClient, ResponseMessage, Tool};
-use serde::{Deserialize, Serialize};
+use common::names::{MAX_TOOL_NAME_ID_LEN, MAX_TOOL_NAME_LEN};
+use common::{AgentId, ChatMessage, FunctionDefinition, LlmClient, ResponseMessage, Tool};
 use serde_json::json;
-use tracing::{debug, error};
+use tracing::debug;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TaskAssignment {
-    pub agent_id: String,
-    pub goal: String,
-    pub reasoning: String,
-}
+/// Prefix of the per-agent tools; the rest is [`AgentId::to_tool_name`]
+pub const ASSIGN_TOOL_PREFIX: &str = "assign_to_";
 
-#[derive(Debug, Clone)]
-pub enum FacilitatorAction {
-    AssignTask(TaskAssignment),
-    ReplyDirectly(String),
-}
-
-pub struct AnalysisResult {
-    pub actions: Vec<FacilitatorAction>,
-}
+// Every agent's tool name stays within the LLM API's limit
+const _: () = assert!(ASSIGN_TOOL_PREFIX.len() + MAX_TOOL_NAME_ID_LEN <= MAX_TOOL_NAME_LEN);
 
 pub struct FacilitatorLlm {
     client: LlmClient,
-    model: String,
 }
 
 impl FacilitatorLlm {
     pub fn new(api_key: String, model: String, base_url: String) -> Self {
-        let client = LlmClient::new(api_key, model.clone(), base_url);
-        Self { client, model }
+        let client = LlmClient::new(api_key, model, base_url);
+        Self { client }
     }
 
     /// Execute facilitator logic: analyze conversation context and determine task assignments
     pub async fn execute(
         &self,
         context: &[ChatMessage],
-        available_agents: &[(String, Option<String>)], // (agent_id, description)
+        available_agents: &[(AgentId, Option<String>)], // (agent_id, description)
     ) -> Result<ResponseMessage, Box<dyn std::error::Error>> {
         let system_prompt = self.build_system_prompt(available_agents);
 
@@ -63,7 +51,7 @@ impl FacilitatorLlm {
                 Tool {
                     tool_type: "function".to_string(),
                     function: FunctionDefinition {
-                        name: format!("assign_to_{}", agent_id.replace("-", "_")),
+                        name: format!("{}{}", ASSIGN_TOOL_PREFIX, agent_id.to_tool_name()),
                         description: desc,
                         parameters: json!({
                             "type": "object",
@@ -112,7 +100,7 @@ impl FacilitatorLlm {
         }
     }
 
-    fn build_system_prompt(&self, available_agents: &[(String, Option<String>)]) -> String {
+    fn build_system_prompt(&self, available_agents: &[(AgentId, Option<String>)]) -> String {
         let agents_list = if available_agents.is_empty() {
             "No agents currently available.".to_string()
         } else {
@@ -158,12 +146,12 @@ WHEN TO STAY SILENT (return empty string "", NO function call):
 Examples:
 User: "hello" → Respond: "Hi! I coordinate tasks between you and our specialist agents. What can I help with?"
 User: "how are you?" → Respond: "I'm doing well, thanks! Ready to help coordinate any tasks you need."
-User: "what's 1+1?" → Call assign_to_math_agent (don't answer yourself)
+User: "what's 1+1?" → Call assign_to_math-agent (don't answer yourself)
 User: "thanks!" → Respond: "You're welcome!"
 Math-agent posts final result "The sum is 2" → Return "" (stay silent)
-User: "now double it" → Call assign_to_math_agent (with context: previous answer was 2)
+User: "now double it" → Call assign_to_math-agent (with context: previous answer was 2)
 "#,
             agents_list
         )
     }
-}
\ No newline at end of file
+}
@@ -10,15 +10,16 @@ mod llm;
 use agent_registry::AgentRegistry;
 use clap::Parser;
 use common::message::{
-    AckContent, FromKind, HeartbeatPayload, MicGrantPayload, MicRevokePayload, ResultContent,
-    ResultMessageType, ResultOutcome, ResultPayload, SayPayload, TaskPayload,
+    AckContent, FromKind, MicGrantPayload, MicRevokePayload, ResultContent, ResultMessageType,
+    ResultOutcome, TaskPayload,
 };
-use common::{topics, Envelope, EnvelopeType, MessageHistory, Sender, SenderKind};
+use common::runtime::{Component, Context, Identity, MqttSettings, Runtime};
+use common::time::now_secs;
+use common::{topics, AgentId, Envelope, MessageHistory, Payload, Sender, Signer, Topic};
 use config::FacilitatorConfig;
-use llm::FacilitatorLlm;
-use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use llm::{FacilitatorLlm, ASSIGN_TOOL_PREFIX};
+use std::collections::HashMap;
+use std::time::Duration;
 use tracing::{error, info, warn};
 
 #[tokio::main]
@@ -29,156 +30,170 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Facilitator starting");
     info!("  Room ID: {}", config.room_id);
+    info!("  All rooms: {}", config.all_rooms);
     info!("  MQTT: {}:{}", config.mqtt_host, config.mqtt_port);
     info!("  LLM: {}", config.openai_model);
 
-    // Connect to MQTT
-    let mut mqtt_options = MqttOptions::new("facilitator", &config.mqtt_host, config.mqtt_port);
-    mqtt_options.set_keep_alive(std::time::Duration::from_secs(30));
-    let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
-
-    // Subscribe to topics
-    let public_topic = topics::public(&config.room_id);
-    let heartbeat_topic = topics::all_agent_heartbeats(&config.room_id);
-    client.subscribe(&public_topic, QoS::AtLeastOnce).await?;
-    client.subscribe(&heartbeat_topic, QoS::AtLeastOnce).await?;
-
-    info!("Subscribed to:");
-    info!("  {}", public_topic);
-    info!("  {}", heartbeat_topic);
-
-    // Initialize conversation memory
-    let memory = Arc::new(Mutex::new(MessageHistory::new(50)));
-
-    // Specific Initializers
-    let mut agent_registry = AgentRegistry::new(config.agent_heartbeat_timeout_secs);
-    let mut next_task_id = 0u64;
-    let llm_client = FacilitatorLlm::new(
-        config.openai_api_key.clone(),
-        config.openai_model.clone(),
-        config.openai_base_url.clone(),
-    );
+    let mut identity = Identity::new(
+        topics::room_filter(&config.room_id, config.all_rooms),
+        Sender::agent("facilitator"),
+        "Facilitator - coordinates tasks and assigns work to agents",
+    )
+    .heartbeat_every(Duration::from_secs(5));
+    if let Some(path) = &config.signing_key {
+        let signer = Signer::load("facilitator", path)?;
+        info!(
+            "  Signing key: {} (public key {})",
+            path,
+            signer.public_key()
+        );
+        identity = identity.signed_with(signer);
+    }
+    let mqtt = MqttSettings {
+        host: config.mqtt_host.clone(),
+        port: config.mqtt_port,
+        client_id: "facilitator".to_string(),
+        keep_alive_secs: 30,
+    };
 
     info!("Facilitator running");
 
-    // Spawn heartbeat task
-    let client_clone = client.clone();
-    let room_id = config.room_id.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
-        loop {
-            interval.tick().await;
-            let now = now_secs();
-            let heartbeat = Envelope {
-                id: format!("facilitator_heartbeat_{}", now),
-                message_type: EnvelopeType::Heartbeat,
-                room_id: room_id.clone(),
-                from: Sender {
-                    kind: SenderKind::Agent,
-                    id: "facilitator".to_string(),
-                },
-                ts: now,
-                payload: serde_json::to_value(HeartbeatPayload {
-                    ts: now,
-                    description: Some(
-                        "Facilitator - coordinates tasks and assigns work to agents".to_string(),
-                    ),
-                    can_accept_tasks: false,
-                })
-                .unwrap(),
-            };
-            let topic = format!("rooms/{}/agents/facilitator/heartbeat", room_id);
-            let _ = client_clone
-                .publish(
-                    topic,
-                    QoS::AtLeastOnce,
-                    false,
-                    serde_json::to_vec(&heartbeat).unwrap(),
-                )
-                .await;
+    Runtime::new(identity, mqtt)
+        .run(Facilitator::new(config))
+        .await?;
+    Ok(())
+}
+
+struct Facilitator {
+    rooms: HashMap<String, RoomState>,
+    next_task_id: u64,
+    llm_client: FacilitatorLlm,
+    config: FacilitatorConfig,
+}
+
+/// Everything the facilitator knows about one room
+struct RoomState {
+    // Conversation memory
+    memory: MessageHistory,
+    agent_registry: AgentRegistry,
+}
+
+impl Facilitator {
+    fn new(config: FacilitatorConfig) -> Self {
+        let llm_client = FacilitatorLlm::new(
+            config.openai_api_key.clone(),
+            config.openai_model.clone(),
+            config.openai_base_url.clone(),
+        );
+
+        Self {
+            rooms: HashMap::new(),
+            next_task_id: 0,
+            llm_client,
+            config,
         }
-    });
-
-    // Main event loop
-    loop {
-        match event_loop.poll().await {
-            Ok(Event::Incoming(Packet::Publish(p))) => {
-                if p.topic == public_topic {
-                    handle_user_message(
-                        &p.payload,
-                        &config,
-                        &client,
-                        &mut next_task_id,
-                        &llm_client,
-                        &agent_registry,
-                        &memory,
-                    )
-                    .await;
-                } else if p.topic.ends_with("/heartbeat") {
-                    handle_heartbeat(&p.topic, &p.payload, &mut agent_registry);
-                }
-            }
-            Ok(_) => {}
+    }
+}
+
+impl Component for Facilitator {
+    fn subscriptions(&self) -> Vec<String> {
+        let rooms = topics::room_filter(&self.config.room_id, self.config.all_rooms);
+        vec![
+            topics::public(rooms),
+            topics::control(rooms),
+            topics::all_agent_heartbeats(rooms),
+        ]
+    }
+
+    async fn on_envelope(&mut self, ctx: &Context, topic: &str, envelope: Envelope) {
+        let topic = match Topic::parse(topic) {
+            Ok(topic) => topic,
             Err(e) => {
-                error!("MQTT error: {}", e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                warn!("{}", e);
+                return;
             }
+        };
+        let room_id = topic.room_id();
+        let timeout_secs = self.config.agent_heartbeat_timeout_secs;
+        let room = self
+            .rooms
+            .entry(room_id.to_string())
+            .or_insert_with(|| RoomState {
+                memory: MessageHistory::new(50),
+                agent_registry: AgentRegistry::new(timeout_secs),
+            });
+
+        match topic {
+            Topic::Public { .. } => {
+                handle_user_message(
+                    envelope,
+                    room_id,
+                    ctx,
+                    &self.config,
+                    &mut self.next_task_id,
+                    &self.llm_client,
+                    room,
+                )
+                .await
+            }
+            Topic::Heartbeat { agent_id, .. } => {
+                handle_heartbeat(agent_id, envelope, &mut room.agent_registry)
+            }
+            Topic::Control { .. } => {
+                if let Payload::MicExpired(expired) = &envelope.payload {
+                    info!(
+                        "Agent {} lost the mic for {} ({})",
+                        expired.agent_id, expired.task_id, expired.reason
+                    );
+                }
+            }
+            _ => {}
         }
     }
 }
 
-fn handle_heartbeat(topic: &str, payload: &[u8], agent_registry: &mut AgentRegistry) {
-    // Extract agent_id from: rooms/{roomId}/agents/{agentId}/heartbeat
-    if let Some(agent_id) = topic.split('/').nth(3) {
-        if let Ok(envelope) = serde_json::from_slice::<Envelope>(payload) {
-            if envelope.message_type == EnvelopeType::Heartbeat {
-                if let Ok(heartbeat) =
-                    serde_json::from_value::<common::message::HeartbeatPayload>(envelope.payload)
-                {
-                    // Only register agents that can accept tasks
-                    if heartbeat.can_accept_tasks {
-                        agent_registry.update_agent(agent_id.to_string(), heartbeat.description);
-                    }
-                }
+fn handle_heartbeat(agent_id: &str, envelope: Envelope, agent_registry: &mut AgentRegistry) {
+    // Only register agents that can accept tasks
+    if let Payload::Heartbeat(heartbeat) = envelope.payload {
+        if heartbeat.can_accept_tasks {
+            match AgentId::new(agent_id) {
+                Ok(agent_id) => agent_registry.update_agent(agent_id, heartbeat.description),
+                Err(e) => warn!("Ignoring heartbeat: {}", e),
             }
         }
     }
 }
 
 async fn handle_user_message(
-    payload: &[u8],
+    envelope: Envelope,
+    room_id: &str,
+    ctx: &Context,
     config: &FacilitatorConfig,
-    client: &AsyncClient,
     next_task_id: &mut u64,
     llm_client: &FacilitatorLlm,
-    agent_registry: &AgentRegistry,
-    memory: &Arc<Mutex<MessageHistory>>,
+    room: &mut RoomState,
 ) {
-    // Parse envelope
-    let Ok(envelope) = serde_json::from_slice::<Envelope>(payload) else {
-        return;
-    };
+    let RoomState {
+        memory,
+        agent_registry,
+    } = room;
+
+    // Everything sent in response joins the thread of the triggering message
+    let envelopes = ctx.envelopes_in(room_id).caused_by(&envelope);
 
     // Store all public messages in memory
-    {
-        let mut mem = memory.lock().await;
-        mem.add(envelope.clone());
-    }
+    memory.add(envelope.clone());
 
     // Process 'say' messages from users OR final 'result' messages from OTHER agents (not facilitator)
-    let should_process = match (&envelope.message_type, &envelope.from.kind) {
-        (EnvelopeType::Say, FromKind::User) => true,
-        (EnvelopeType::Result, FromKind::Agent) => {
+    let should_process = match (&envelope.payload, &envelope.from.kind) {
+        (Payload::Say(_), FromKind::User) => true,
+        (Payload::Result(result), FromKind::Agent) => {
             // Don't process our own messages!
             if envelope.from.id == "facilitator" {
                 return;
             }
             // Only process final results, not ack/progress/etc
-            if let Ok(result) = serde_json::from_value::<ResultPayload>(envelope.payload.clone()) {
-                result.message_type == ResultMessageType::Result
-            } else {
-                false
-            }
+            result.message_type == ResultMessageType::Result
         }
         _ => false,
     };
@@ -188,101 +203,43 @@ async fn handle_user_message(
     }
 
     // Log what we're processing
-    match &envelope.message_type {
-        EnvelopeType::Say => {
-            if let Ok(say) = serde_json::from_value::<SayPayload>(envelope.payload.clone()) {
-                info!("User: {}", say.text);
-            }
+    match &envelope.payload {
+        Payload::Say(say) => {
+            info!("User: {}", say.text);
         }
-        EnvelopeType::Result => {
-            if let Ok(result) = serde_json::from_value::<ResultPayload>(envelope.payload.clone()) {
-                info!("Agent final result received (task: {})", result.task_id);
-
-                // Send ack immediately to show we're processing the agent's result
-                let now = now_secs();
-                let ack_envelope = Envelope {
-                    id: format!("ack_{}", now),
-                    message_type: EnvelopeType::Result,
-                    room_id: config.room_id.clone(),
-                    from: Sender {
-                        kind: SenderKind::Agent,
-                        id: "facilitator".to_string(),
-                    },
-                    ts: now,
-                    payload: serde_json::to_value(ResultPayload {
-                        task_id: format!("facilitator_ack_{}", now),
-                        message_type: ResultMessageType::Ack,
-                        content: ResultContent::Ack(AckContent {
-                            text: "Received agent result...".to_string(),
-                        }),
-                    })
-                    .unwrap(),
-                };
-                let _ = client
-                    .publish(
-                        topics::public(&config.room_id),
-                        QoS::AtLeastOnce,
-                        false,
-                        serde_json::to_vec(&ack_envelope).unwrap(),
-                    )
-                    .await;
+        Payload::Result(result) => {
+            info!("Agent final result received (task: {})", result.task_id);
 
-                // Revoke mic grant for completed task
-                let revoke_envelope = Envelope {
-                    id: format!("revoke_{}", now),
-                    message_type: EnvelopeType::MicRevoke,
-                    room_id: config.room_id.clone(),
-                    from: Sender {
-                        kind: SenderKind::Agent,
-                        id: "facilitator".to_string(),
-                    },
-                    ts: now,
-                    payload: serde_json::to_value(MicRevokePayload {
-                        task_id: result.task_id.clone(),
-                        agent_id: envelope.from.id.clone(),
-                    })
-                    .unwrap(),
-                };
-                let _ = client
-                    .publish(
-                        topics::control(&config.room_id),
-                        QoS::AtLeastOnce,
-                        false,
-                        serde_json::to_vec(&revoke_envelope).unwrap(),
-                    )
-                    .await;
+            // Send ack immediately to show we're processing the agent's result
+            let now = now_secs();
+            let ack_envelope = envelopes.result(
+                format!("facilitator_ack_{}", now),
+                ResultContent::Ack(AckContent {
+                    text: "Received agent result...".to_string(),
+                }),
+            );
+            let _ = ctx.publish(topics::public(room_id), &ack_envelope).await;
+
+            // Revoke mic grant for completed task
+            let revoke_envelope = envelopes.mic_revoke(MicRevokePayload {
+                task_id: result.task_id.clone(),
+                agent_id: envelope.from.id.clone(),
+            });
+            let _ = ctx
+                .publish(topics::control(room_id), &revoke_envelope)
+                .await;
 
-                // Send empty result to signal completion
-                info!("→ Silent completion (agent task complete)");
-                let now = now_secs();
-                let result_envelope = Envelope {
-                    id: format!("result_agent_complete_{}", now),
-                    message_type: EnvelopeType::Result,
-                    room_id: config.room_id.clone(),
-                    from: Sender {
-                        kind: SenderKind::Agent,
-                        id: "facilitator".to_string(),
-                    },
-                    ts: now,
-                    payload: serde_json::to_value(ResultPayload {
-                        task_id: format!("facilitator_result_{}", now),
-                        message_type: ResultMessageType::Result,
-                        content: ResultContent::Result(ResultOutcome {
-                            text: "".to_string(),
-                        }),
-                    })
-                    .unwrap(),
-                };
-                let _ = client
-                    .publish(
-                        topics::public(&config.room_id),
-                        QoS::AtLeastOnce,
-                        false,
-                        serde_json::to_vec(&result_envelope).unwrap(),
-                    )
-                    .await;
-                return;
-            }
+            // Send empty result to signal completion
+            info!("→ Silent completion (agent task complete)");
+            let now = now_secs();
+            let result_envelope = envelopes.result(
+                format!("facilitator_result_{}", now),
+                ResultContent::Result(ResultOutcome {
+                    text: "".to_string(),
+                }),
+            );
+            let _ = ctx.publish(topics::public(room_id), &result_envelope).await;
+            return;
         }
         _ => {}
     }
@@ -297,169 +254,106 @@ async fn handle_user_message(
     info!("Active agents: {}", active_agents.join(", "));
 
     // Get conversation context from memory
-    let context = {
-        let mem = memory.lock().await;
-        mem.to_chat_messages()
-    };
+    let context = memory.to_chat_messages();
 
     // Get active agents with descriptions
     let agents_with_desc = agent_registry.get_active_agents_with_descriptions();
 
     // Send ack immediately to show we're processing
     let now = now_secs();
-    let ack_envelope = Envelope {
-        id: format!("facilitator_ack_{}", now),
-        message_type: EnvelopeType::Result,
-        room_id: config.room_id.clone(),
-        from: Sender {
-            kind: SenderKind::Agent,
-            id: "facilitator".to_string(),
-        },
-        ts: now,
-        payload: serde_json::to_value(ResultPayload {
-            task_id: format!("facilitator_task_{}", now),
-            message_type: ResultMessageType::Ack,
-            content: ResultContent::Ack(AckContent {
-                text: "Processing your request...".to_string(),
+    let ack_envelope = envelopes.result(
+        format!("facilitator_task_{}", now),
+        ResultContent::Ack(AckContent {
+            text: "Processing your request...".to_string(),
+        }),
+    );
+    let _ = ctx.publish(topics::public(room_id), &ack_envelope).await;
+
+    // Execute facilitator logic
+    let response_msg = match llm_client.execute(&context, &agents_with_desc).await {
+        Ok(msg) => {
+            let tool_count = msg.tool_calls.as_ref().map(|c| c.len()).unwrap_or(0);
+            info!("LLM returned {} tool call(s)", tool_count);
+            msg
+        }
+        Err(e) => {
+            error!("LLM analysis failed: {}", e);
+            return;
+        }
+    };
+
+    // If no tool calls, send direct response (or empty result for state update)
+    let Some(tool_calls) = response_msg.tool_calls.as_ref() else {
+        let text = response_msg
+            .content
+            .as_ref()
+            .map(|s| s.trim())
+            .unwrap_or("");
+
+        if !text.is_empty() {
+            info!("→ Direct reply: {}", text);
+        } else {
+            info!("→ Silent completion (no message needed)");
+        }
+
+        // Always send a result (even if empty) so UI can update state
+        let envelope = envelopes.result(
+            "facilitator_response",
+            ResultContent::Result(ResultOutcome {
+                text: text.to_string(),
             }),
-        })
-        .unwrap(),
+        );
+        let _ = ctx.publish(topics::public(room_id), &envelope).await;
+        return;
     };
-    let _ = client
-        .publish(
-            topics::public(&config.room_id),
-            QoS::AtLeastOnce,
-            false,
-            serde_json::to_vec(&ack_envelope).unwrap(),
-        )
-        .await;
-
-    // Agentic loop: keep executing until no tool calls (task assignments) are made
-    loop {
-        // Execute facilitator logic
-        let response_msg = match llm_client.execute(&context, &agents_with_desc).await {
-            Ok(msg) => {
-                let tool_count = msg.tool_calls.as_ref().map(|c| c.len()).unwrap_or(0);
-                info!("LLM returned {} tool call(s)", tool_count);
-                msg
-            }
-            Err(e) => {
-                error!("LLM analysis failed: {}", e);
-                return;
-            }
-        };
 
-        // If no tool calls, send direct response (or empty result for state update)
-        let Some(tool_calls) = response_msg.tool_calls.as_ref() else {
-            let now = now_secs();
-            let text = response_msg
-                .content
-                .as_ref()
-                .map(|s| s.trim())
-                .unwrap_or("");
-
-            if !text.is_empty() {
-                info!("→ Direct reply: {}", text);
-            } else {
-                info!("→ Silent completion (no message needed)");
-            }
+    // Process tool calls (task assignments)
+    info!("Processing {} task assignment(s)", tool_calls.len());
+    let mut tool_result_msgs = Vec::new();
+
+    for tool_call in tool_calls {
+        // Extract agent_id from function name: assign_to_{encoded agent_id}
+        let agent_id = tool_call
+            .function
+            .name
+            .strip_prefix(ASSIGN_TOOL_PREFIX)
+            .and_then(|encoded| AgentId::from_tool_name(encoded).ok());
+        if let Some(agent_id) = agent_id {
+            // Parse the arguments (goal and reasoning)
+            let args: serde_json::Value =
+                serde_json::from_str(&tool_call.function.arguments).unwrap_or_default();
+
+            let goal = args
+                .get("goal")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+
+            if !goal.is_empty() {
+                info!("→ @{}: {}", agent_id, goal);
+
+                let task_id = format!("task_{}", *next_task_id);
+                *next_task_id += 1;
+                let now = now_secs();
 
-            // Always send a result (even if empty) so UI can update state
-            let envelope = Envelope {
-                id: format!("facilitator_{}", now),
-                message_type: EnvelopeType::Result,
-                room_id: config.room_id.clone(),
-                from: Sender {
-                    kind: SenderKind::Agent,
-                    id: "facilitator".to_string(),
-                },
-                ts: now,
-                payload: serde_json::to_value(ResultPayload {
-                    task_id: "facilitator_response".to_string(),
-                    message_type: ResultMessageType::Result,
-                    content: ResultContent::Result(ResultOutcome {
-                        text: text.to_string(),
-                    }),
-                })
-                .unwrap(),
-            };
-            let _ = client
-                .publish(
-                    topics::public(&config.room_id),
-                    QoS::AtLeastOnce,
-                    false,
-                    serde_json::to_vec(&envelope).unwrap(),
-                )
-                .await;
-            return;
-        };
+                // 1. Send task to agent inbox
+                let task_envelope = envelopes.task(TaskPayload {
+                    task_id: task_id.clone(),
+                    goal: goal.clone(),
+                    format: None,
+                    deadline: Some(now + 300),
+                });
+                let _ = ctx
+                    .publish(topics::agent_inbox(room_id, &agent_id), &task_envelope)
+                    .await;
 
-        // Process tool calls (task assignments)
-        info!("Processing {} task assignment(s)", tool_calls.len());
-        let mut tool_result_msgs = Vec::new();
-
-        for tool_call in tool_calls {
-            // Extract agent_id from function name: assign_to_{agent_id}
-            if let Some(agent_id) = tool_call.function.name.strip_prefix("assign_to_") {
-                let agent_id = agent_id.replace("_", "-");
-
-                // Parse the arguments (goal and reasoning)
-                let args: serde_json::Value =
-                    serde_json::from_str(&tool_call.function.arguments).unwrap_or_default();
-
-                let goal = args
-                    .get("goal")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-
-                if !goal.is_empty() {
-                    info!("→ @{}: {}", agent_id, goal);
-
-                    let task_id = format!("task_{}", *next_task_id);
-                    *next_task_id += 1;
-                    let now = now_secs();
-
-                    // 1. Send task to agent inbox
-                    let task_envelope = Envelope {
-                        id: format!("task_{}", task_id),
-                        message_type: EnvelopeType::Task,
-                        room_id: config.room_id.clone(),
-                        from: Sender {
-                            kind: SenderKind::Agent,
-                            id: "facilitator".to_string(),
-                        },
-                        ts: now,
-                        payload: serde_json::to_value(TaskPayload {
-                            task_id: task_id.clone(),
-                            goal: goal.clone(),
-                            format: None,
-                            deadline: Some(now + 300),
-                        })
-                        .unwrap(),
-                    };
-                    let _ = client
-                        .publish(
-                            topics::agent_inbox(&config.room_id, &agent_id),
-                            QoS::AtLeastOnce,
-                            false,
-                            serde_json::to_vec(&task_envelope).unwrap(),
-                        )
-                        .await;
-
-                    // 2. Issue mic grant
-                    let grant_envelope = Envelope {
-                        id: format!("grant_{}", task_id),
-                        message_type: EnvelopeType::MicGrant,
-                        room_id: config.room_id.clone(),
-                        from: Sender {
-                            kind: SenderKind::Agent,
-                            id: "facilitator".to_string(),
-                        },
-                        ts: now,
-                        payload: serde_json::to_value(MicGrantPayload {
+                // 2. Issue mic grant
+                let grant_envelope =
+                    envelopes
+                        .caused_by(&task_envelope)
+                        .mic_grant(MicGrantPayload {
                             task_id: task_id.clone(),
-                            agent_id: agent_id.clone(),
+                            agent_id: agent_id.to_string(),
                             max_messages: config.default_max_messages,
                             allowed_message_types: vec![
                                 ResultMessageType::Ack,
@@ -471,78 +365,41 @@ async fn handle_user_message(
                                 ResultMessageType::ArtifactLink,
                             ],
                             expires_at: now + config.default_mic_duration_secs,
-                        })
-                        .unwrap(),
-                    };
-                    let _ = client
-                        .publish(
-                            topics::control(&config.room_id),
-                            QoS::AtLeastOnce,
-                            false,
-                            serde_json::to_vec(&grant_envelope).unwrap(),
-                        )
-                        .await;
-
-                    // Add tool result
-                    tool_result_msgs.push(serde_json::json!({
-                        "role": "tool",
-                        "tool_call_id": tool_call.id,
-                        "content": format!("Task {} assigned to {} successfully", task_id, agent_id)
-                    }));
-                } else {
-                    warn!("Empty goal in tool call");
-                    tool_result_msgs.push(serde_json::json!({
-                        "role": "tool",
-                        "tool_call_id": tool_call.id,
-                        "content": "Error: goal cannot be empty"
-                    }));
-                }
+                        });
+                let _ = ctx.publish(topics::control(room_id), &grant_envelope).await;
+
+                // Add tool result
+                tool_result_msgs.push(serde_json::json!({
+                    "role": "tool",
+                    "tool_call_id": tool_call.id,
+                    "content": format!("Task {} assigned to {} successfully", task_id, agent_id)
+                }));
             } else {
-                warn!("Unknown tool: {}", tool_call.function.name);
+                warn!("Empty goal in tool call");
                 tool_result_msgs.push(serde_json::json!({
                     "role": "tool",
                     "tool_call_id": tool_call.id,
-                    "content": format!("Error: unknown tool '{}'", tool_call.function.name)
+                    "content": "Error: goal cannot be empty"
                 }));
             }
+        } else {
+            warn!("Unknown tool: {}", tool_call.function.name);
+            tool_result_msgs.push(serde_json::json!({
+                "role": "tool",
+                "tool_call_id": tool_call.id,
+                "content": format!("Error: unknown tool '{}'", tool_call.function.name)
+            }));
         }
-
-        // Tasks assigned - send empty result to signal completion of handoff
-        info!("Silent completion (task handoff)");
-        let now = now_secs();
-        let result_envelope = Envelope {
-            id: format!("result_handoff_{}", now),
-            message_type: EnvelopeType::Result,
-            room_id: config.room_id.clone(),
-            from: Sender {
-                kind: SenderKind::Agent,
-                id: "facilitator".to_string(),
-            },
-            ts: now,
-            payload: serde_json::to_value(ResultPayload {
-                task_id: format!("facilitator_handoff_{}", now),
-                message_type: ResultMessageType::Result,
-                content: ResultContent::Result(ResultOutcome {
-                    text: "".to_string(),
-                }),
-            })
-            .unwrap(),
-        };
-        let _ = client
-            .publish(
-                topics::public(&config.room_id),
-                QoS::AtLeastOnce,
-                false,
-                serde_json::to_vec(&result_envelope).unwrap(),
-            )
-            .await;
-        return;
     }
-}
 
-fn now_secs() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+    // Tasks assigned - send empty result to signal completion of handoff
+    info!("Silent completion (task handoff)");
+    let now = now_secs();
+    let result_envelope = envelopes.result(
+        format!("facilitator_handoff_{}", now),
+        ResultContent::Result(ResultOutcome {
+            text: "".to_string(),
+        }),
+    );
+    let _ = ctx.publish(topics::public(room_id), &result_envelope).await;
 }
@@ -0,0 +1,449 @@
+//! Tamper-evident audit log
+//!
+//! Every approval, rejection and hold is appended to a JSONL file as one
+//! record. Each record carries the SHA-256 hash of the previous record and its
+//! own hash over its contents, so editing, removing or reordering records
+//! breaks the chain from that point on. `gateway verify-audit <file>` checks
+//! the chain and prints its head hash; keeping a copy of the head elsewhere
+//! also catches records cut off the end.
+//!
+//! Each record is synced to disk before the gateway acts on it. A crash
+//! partway through writing one leaves a final line without its newline; the
+//! next start moves that line to `<file>.torn` and continues the chain from
+//! the record before it.
+
+use common::MicGrantState;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// `prev_hash` of the first record
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What the gateway did with a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditDecision {
+    Approved,
+    Rejected,
+    Held,
+}
+
+/// One decision, as hashed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, starting at 1
+    pub seq: u64,
+    pub ts: u64,
+    pub room_id: String,
+    pub message_id: String,
+    pub agent_id: String,
+    pub task_id: String,
+    pub decision: AuditDecision,
+    /// Rejection reason code, hold rule, or approving moderator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// The sender's mic grant for the task after the decision, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grant: Option<MicGrantState>,
+    pub prev_hash: String,
+}
+
+impl AuditEntry {
+    fn hash(&self) -> String {
+        let bytes = serde_json::to_vec(self).expect("audit entries serialize");
+        Sha256::digest(&bytes)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// A line of the log
+#[derive(Debug, Serialize, Deserialize)]
+struct AuditRecord {
+    #[serde(flatten)]
+    entry: AuditEntry,
+    hash: String,
+}
+
+/// A decision to record; the log fills in sequence, time and hashes
+#[derive(Debug)]
+pub struct Decision {
+    pub room_id: String,
+    pub message_id: String,
+    pub agent_id: String,
+    pub task_id: String,
+    pub decision: AuditDecision,
+    pub reason: Option<String>,
+    pub detail: Option<String>,
+    pub grant: Option<MicGrantState>,
+}
+
+/// Appends decisions to the end of the chain
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: File,
+    next_seq: u64,
+    last_hash: String,
+}
+
+impl AuditLog {
+    /// Open a log for appending, continuing the chain of any existing records
+    ///
+    /// A torn last record is quarantined rather than keeping the gateway
+    /// down; any other invalid last record is an error.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("failed to open audit log {}: {}", path.display(), e))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|e| format!("failed to read audit log {}: {}", path.display(), e))?;
+
+        let mut last = last_line(&bytes, bytes.len());
+        if let Some((start, line)) = last {
+            let complete = bytes.ends_with(b"\n");
+            match serde_json::from_slice::<AuditRecord>(line) {
+                Ok(_) if !complete => {
+                    // Only the newline was lost
+                    file.write_all(b"\n")
+                        .and_then(|()| file.sync_data())
+                        .map_err(|e| {
+                            format!("failed to repair audit log {}: {}", path.display(), e)
+                        })?;
+                }
+                Err(_) if !complete => {
+                    quarantine(&path, &mut file, start, &bytes[start..])?;
+                    last = last_line(&bytes, start);
+                }
+                _ => {}
+            }
+        }
+
+        let (next_seq, last_hash) = match last {
+            Some((_, line)) => {
+                let record: AuditRecord = serde_json::from_slice(line).map_err(|e| {
+                    format!(
+                        "audit log {} ends with an invalid record: {}",
+                        path.display(),
+                        e
+                    )
+                })?;
+                (record.entry.seq + 1, record.hash)
+            }
+            None => (1, GENESIS_HASH.to_string()),
+        };
+        Ok(Self {
+            path,
+            file,
+            next_seq,
+            last_hash,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a decision made at `ts`
+    pub fn append(&mut self, decision: Decision, ts: u64) -> std::io::Result<()> {
+        let entry = AuditEntry {
+            seq: self.next_seq,
+            ts,
+            room_id: decision.room_id,
+            message_id: decision.message_id,
+            agent_id: decision.agent_id,
+            task_id: decision.task_id,
+            decision: decision.decision,
+            reason: decision.reason,
+            detail: decision.detail,
+            grant: decision.grant,
+            prev_hash: self.last_hash.clone(),
+        };
+        let hash = entry.hash();
+        let mut line = serde_json::to_vec(&AuditRecord {
+            entry,
+            hash: hash.clone(),
+        })?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+
+        self.next_seq += 1;
+        self.last_hash = hash;
+        Ok(())
+    }
+}
+
+/// Start and contents of the last non-blank line before `end`
+fn last_line(bytes: &[u8], end: usize) -> Option<(usize, &[u8])> {
+    let end = bytes[..end]
+        .iter()
+        .rposition(|byte| !byte.is_ascii_whitespace())?
+        + 1;
+    let start = bytes[..end]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    Some((start, &bytes[start..end]))
+}
+
+/// Move a torn record at `start` out of the log into `<log>.torn`
+fn quarantine(path: &Path, file: &mut File, start: usize, torn: &[u8]) -> Result<(), String> {
+    let mut torn_path = path.as_os_str().to_owned();
+    torn_path.push(".torn");
+    let torn_path = PathBuf::from(torn_path);
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&torn_path)
+        .and_then(|mut quarantined| {
+            quarantined.write_all(torn)?;
+            quarantined.write_all(b"\n")?;
+            quarantined.sync_data()
+        })
+        .and_then(|()| file.set_len(start as u64))
+        .and_then(|()| file.sync_data())
+        .map_err(|e| {
+            format!(
+                "failed to quarantine torn record in audit log {}: {}",
+                path.display(),
+                e
+            )
+        })?;
+    warn!(
+        "Audit log {} ended with a partially written record; moved it to {} \
+         and resumed from the record before it",
+        path.display(),
+        torn_path.display()
+    );
+    Ok(())
+}
+
+/// Summary of an intact log
+#[derive(Debug)]
+pub struct Verified {
+    pub records: u64,
+    /// Hash of the last record, or the genesis hash of an empty log
+    pub head: String,
+}
+
+/// Check every record's hash and link, failing at the first broken one
+pub fn verify(path: impl AsRef<Path>) -> Result<Verified, String> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .map_err(|e| format!("failed to open audit log {}: {}", path.display(), e))?;
+
+    let mut records = 0;
+    let mut head = GENESIS_HASH.to_string();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.map_err(|e| format!("line {}: {}", line_no, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: AuditRecord = serde_json::from_str(&line)
+            .map_err(|e| format!("line {}: invalid record: {}", line_no, e))?;
+        let entry = &record.entry;
+
+        if entry.seq != records + 1 {
+            return Err(format!(
+                "line {}: expected record {}, found {}",
+                line_no,
+                records + 1,
+                entry.seq
+            ));
+        }
+        if entry.prev_hash != head {
+            return Err(format!(
+                "line {}: record {} does not follow the previous record",
+                line_no, entry.seq
+            ));
+        }
+        let reserialized = serde_json::to_string(&record).unwrap_or_default();
+        if entry.hash() != record.hash || reserialized != line.trim_end() {
+            return Err(format!(
+                "line {}: record {} was modified",
+                line_no, entry.seq
+            ));
+        }
+        records += 1;
+        head = record.hash;
+    }
+    Ok(Verified { records, head })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(message_id: &str, decision: AuditDecision) -> Decision {
+        Decision {
+            room_id: "ops".to_string(),
+            message_id: message_id.to_string(),
+            agent_id: "math".to_string(),
+            task_id: "task_1".to_string(),
+            decision,
+            reason: None,
+            detail: None,
+            grant: None,
+        }
+    }
+
+    /// A log of three decisions, and its lines
+    fn written(dir: &tempfile::TempDir) -> (PathBuf, Vec<String>) {
+        let path = dir.path().join("audit.jsonl");
+        let mut log = AuditLog::open(&path).unwrap();
+        log.append(decision("msg_1", AuditDecision::Approved), 100)
+            .unwrap();
+        let mut rejected = decision("msg_2", AuditDecision::Rejected);
+        rejected.reason = Some("no_mic_grant".to_string());
+        log.append(rejected, 101).unwrap();
+        log.append(decision("msg_3", AuditDecision::Held), 102)
+            .unwrap();
+        let lines = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        (path, lines)
+    }
+
+    fn rewrite(path: &Path, lines: &[String]) {
+        std::fs::write(path, lines.join("\n") + "\n").unwrap();
+    }
+
+    #[test]
+    fn test_verify_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(verify(dir.path().join("missing.jsonl"))
+            .unwrap_err()
+            .starts_with("failed to open audit log"));
+
+        let (path, lines) = written(&dir);
+        let verified = verify(&path).unwrap();
+        assert_eq!(verified.records, 3);
+
+        let records: Vec<AuditRecord> = lines
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records[0].entry.prev_hash, GENESIS_HASH);
+        assert_eq!(records[1].entry.prev_hash, records[0].hash);
+        assert_eq!(records[1].entry.reason.as_deref(), Some("no_mic_grant"));
+        assert_eq!(records[2].entry.decision, AuditDecision::Held);
+        assert_eq!(verified.head, records[2].hash);
+    }
+
+    #[test]
+    fn test_verify_detects_modified_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, mut lines) = written(&dir);
+        lines[1] = lines[1].replace("no_mic_grant", "rate_limited");
+        rewrite(&path, &lines);
+        assert_eq!(verify(&path).unwrap_err(), "line 2: record 2 was modified");
+
+        // Rehashing the edit still breaks the link to the next record
+        let mut record: AuditRecord = serde_json::from_str(&lines[1]).unwrap();
+        record.hash = record.entry.hash();
+        lines[1] = serde_json::to_string(&record).unwrap();
+        rewrite(&path, &lines);
+        assert_eq!(
+            verify(&path).unwrap_err(),
+            "line 3: record 3 does not follow the previous record"
+        );
+    }
+
+    #[test]
+    fn test_verify_detects_removed_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, lines) = written(&dir);
+        let head = verify(&path).unwrap().head;
+
+        rewrite(&path, &[lines[0].clone(), lines[2].clone()]);
+        assert_eq!(
+            verify(&path).unwrap_err(),
+            "line 2: expected record 2, found 3"
+        );
+
+        rewrite(
+            &path,
+            &[lines[0].clone(), lines[2].clone(), lines[1].clone()],
+        );
+        assert_eq!(
+            verify(&path).unwrap_err(),
+            "line 2: expected record 2, found 3"
+        );
+
+        // A cut through the last record leaves it unreadable
+        let cut = &lines[2][..lines[2].len() / 2];
+        std::fs::write(&path, format!("{}\n{}\n{}", lines[0], lines[1], cut)).unwrap();
+        assert!(verify(&path)
+            .unwrap_err()
+            .starts_with("line 3: invalid record"));
+
+        // Whole records cut off the end only show in the head hash
+        rewrite(&path, &lines[..2]);
+        let verified = verify(&path).unwrap();
+        assert_eq!(verified.records, 2);
+        assert_ne!(verified.head, head);
+    }
+
+    #[test]
+    fn test_reopen_resumes_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, _) = written(&dir);
+        let head = verify(&path).unwrap().head;
+
+        let mut log = AuditLog::open(&path).unwrap();
+        assert_eq!(log.next_seq, 4);
+        assert_eq!(log.last_hash, head);
+        log.append(decision("msg_4", AuditDecision::Approved), 103)
+            .unwrap();
+        drop(log);
+        assert_eq!(verify(&path).unwrap().records, 4);
+
+        // A complete but invalid last record is tampering, not a crash
+        std::fs::write(&path, "not json\n").unwrap();
+        assert!(AuditLog::open(&path)
+            .unwrap_err()
+            .contains("ends with an invalid record"));
+    }
+
+    #[test]
+    fn test_reopen_after_torn_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, lines) = written(&dir);
+        let torn = &lines[2][..lines[2].len() / 2];
+        std::fs::write(&path, format!("{}\n{}\n{}", lines[0], lines[1], torn)).unwrap();
+
+        let mut log = AuditLog::open(&path).unwrap();
+        assert_eq!(log.next_seq, 3);
+        let quarantined = std::fs::read_to_string(dir.path().join("audit.jsonl.torn")).unwrap();
+        assert_eq!(quarantined, format!("{}\n", torn));
+        log.append(decision("msg_3", AuditDecision::Held), 102)
+            .unwrap();
+        drop(log);
+        assert_eq!(verify(&path).unwrap().records, 3);
+
+        // A complete record that only lost its newline is kept
+        std::fs::write(&path, format!("{}\n{}", lines[0], lines[1])).unwrap();
+        let mut log = AuditLog::open(&path).unwrap();
+        assert_eq!(log.next_seq, 3);
+        log.append(decision("msg_3", AuditDecision::Held), 102)
+            .unwrap();
+        drop(log);
+        assert_eq!(verify(&path).unwrap().records, 3);
+    }
+}
@@ -1,11 +1,16 @@
 //! Gateway configuration
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use common::{AgentId, RoomId};
+use std::net::SocketAddr;
 
 #[derive(Debug, Parser)]
 #[command(name = "gateway")]
 #[command(about = "Gateway - Deterministic moderation and enforcement")]
 pub struct GatewayConfig {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// MQTT broker host
     #[arg(long, env = "AOR_MQTT_HOST", default_value = "localhost")]
     pub mqtt_host: String,
@@ -24,13 +29,169 @@ pub struct GatewayConfig {
 
     /// Room ID
     #[arg(long, env = "AOR_ROOM_ID", default_value = "default")]
-    pub room_id: String,
+    pub room_id: RoomId,
+
+    /// Serve every room via `rooms/+/...` subscriptions instead of only --room-id
+    #[arg(long, env = "AOR_ALL_ROOMS", default_value = "false")]
+    pub all_rooms: bool,
+
+    /// Gateway instance ID, unique within a pool; must start with `gateway`
+    #[arg(long, env = "AOR_GATEWAY_ID", default_value = "gateway")]
+    pub gateway_id: AgentId,
+
+    /// Join a pool of gateways sharing `public_candidates` via `$share/{group}/...`
+    #[arg(long, env = "AOR_GATEWAY_SHARE_GROUP")]
+    pub share_group: Option<String>,
+
+    /// Messages per second each agent may publish (0 disables)
+    #[arg(long, env = "AOR_GATEWAY_AGENT_MESSAGES_PER_SEC", default_value = "2")]
+    pub agent_messages_per_sec: f64,
+
+    /// Messages each agent may publish at once before its rate applies; the
+    /// default fits a whole mic grant of the facilitator's default size
+    #[arg(long, env = "AOR_GATEWAY_AGENT_MESSAGE_BURST", default_value = "10")]
+    pub agent_message_burst: f64,
+
+    /// Candidate bytes per minute each agent may publish (0 disables)
+    #[arg(long, env = "AOR_GATEWAY_AGENT_BYTES_PER_MIN", default_value = "65536")]
+    pub agent_bytes_per_min: f64,
+
+    /// Messages per second all agents in a room may publish together (0 disables)
+    #[arg(long, env = "AOR_GATEWAY_ROOM_MESSAGES_PER_SEC", default_value = "10")]
+    pub room_messages_per_sec: f64,
+
+    /// Messages all agents in a room may publish at once before its rate applies
+    #[arg(long, env = "AOR_GATEWAY_ROOM_MESSAGE_BURST", default_value = "20")]
+    pub room_message_burst: f64,
+
+    /// Candidate bytes per minute all agents in a room may publish together (0 disables)
+    #[arg(long, env = "AOR_GATEWAY_ROOM_BYTES_PER_MIN", default_value = "262144")]
+    pub room_bytes_per_min: f64,
+
+    /// Longest accepted result text field, in characters; the default leaves
+    /// room for an agent's long final answers
+    #[arg(long, env = "AOR_GATEWAY_MAX_TEXT_LEN", default_value = "32000")]
+    pub max_text_len: usize,
+
+    /// Most bullets accepted in a finding
+    #[arg(long, env = "AOR_GATEWAY_MAX_BULLETS", default_value = "20")]
+    pub max_bullets: usize,
+
+    /// Comma-separated URL schemes accepted in artifact links
+    #[arg(
+        long,
+        env = "AOR_GATEWAY_ALLOWED_URL_SCHEMES",
+        value_delimiter = ',',
+        default_value = "https"
+    )]
+    pub allowed_url_schemes: Vec<String>,
+
+    /// JSON file of content policy rules applied to approved messages
+    #[arg(long, env = "AOR_GATEWAY_POLICY_FILE")]
+    pub policy_file: Option<String>,
+
+    /// Comma-separated result message types held for a moderator, e.g. `result`
+    #[arg(long, env = "AOR_GATEWAY_HOLD_MESSAGE_TYPES", value_delimiter = ',')]
+    pub hold_message_types: Vec<String>,
+
+    /// Comma-separated `risk` severities held for a moderator, e.g. `high`
+    #[arg(long, env = "AOR_GATEWAY_HOLD_RISK_SEVERITIES", value_delimiter = ',')]
+    pub hold_risk_severities: Vec<String>,
 
-    /// Maximum message validation time in milliseconds
-    #[arg(long, env = "AOR_GATEWAY_MAX_VALIDATION_TIME_MS", default_value = "100")]
+    /// Comma-separated agents whose messages are all held for a moderator
+    #[arg(long, env = "AOR_GATEWAY_HOLD_AGENTS", value_delimiter = ',')]
+    pub hold_agents: Vec<String>,
+
+    /// Comma-separated user IDs allowed to approve or deny held messages
+    #[arg(long, env = "AOR_GATEWAY_MODERATORS", value_delimiter = ',')]
+    pub moderators: Vec<String>,
+
+    /// Seconds a held message waits for a moderator before it is rejected
+    #[arg(
+        long,
+        env = "AOR_GATEWAY_MODERATION_TIMEOUT_SECS",
+        default_value = "300"
+    )]
+    pub moderation_timeout_secs: u64,
+
+    /// Time budget for validating one message in milliseconds (0 disables);
+    /// messages that exceed it are rejected with `validation_timeout`
+    #[arg(
+        long,
+        env = "AOR_GATEWAY_MAX_VALIDATION_TIME_MS",
+        default_value = "100"
+    )]
     pub max_validation_time_ms: u64,
 
-    /// Whether to emit detailed rejection reasons
+    /// Comma-separated senders allowed to issue mic grants and revokes
+    #[arg(
+        long,
+        env = "AOR_GATEWAY_MIC_ISSUERS",
+        value_delimiter = ',',
+        default_value = "facilitator"
+    )]
+    pub mic_issuers: Vec<String>,
+
+    /// MQTT v5 user property in which the broker supplies the publishing
+    /// client's identity; when set, candidates, mic grants, revokes,
+    /// moderation decisions and mic grant snapshots must be sent as that
+    /// identity
+    #[arg(long, env = "AOR_GATEWAY_IDENTITY_PROPERTY")]
+    pub identity_property: Option<String>,
+
+    /// Base64 Ed25519 secret key file used to sign envelopes sent by this gateway
+    #[arg(long, env = "AOR_SIGNING_KEY")]
+    pub signing_key: Option<String>,
+
+    /// JSON file of senders' base64 Ed25519 public keys; when set, candidates,
+    /// mic grants, revokes, snapshots and moderation decisions must be signed
+    /// by their sender
+    /// (without it, unsigned snapshots are merged with a warning)
+    #[arg(long, env = "AOR_KEY_REGISTRY")]
+    pub key_registry: Option<String>,
+
+    /// Local address to serve Prometheus metrics on at `/metrics`, e.g.
+    /// `127.0.0.1:9464`
+    #[arg(long, env = "AOR_GATEWAY_METRICS_ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// File where mic grants are saved so they survive restarts
+    /// [default: `{gateway_id}-state.json`]
+    #[arg(long, env = "AOR_GATEWAY_STATE_FILE")]
+    pub state_file: Option<String>,
+
+    /// Append-only, hash-chained log of every approval, rejection and hold
+    /// [default: `{gateway_id}-audit.jsonl`]
+    #[arg(long, env = "AOR_GATEWAY_AUDIT_LOG")]
+    pub audit_log: Option<String>,
+
+    /// Whether rejections include a `detail` naming the failed constraint and counters
     #[arg(long, env = "AOR_GATEWAY_VERBOSE_REJECTIONS", default_value = "true")]
     pub verbose_rejections: bool,
 }
+
+impl GatewayConfig {
+    /// State file path, named after the gateway so pool members started in
+    /// the same directory don't overwrite each other's
+    pub fn state_file(&self) -> String {
+        self.state_file
+            .clone()
+            .unwrap_or_else(|| format!("{}-state.json", self.gateway_id))
+    }
+
+    /// Audit log path, named after the gateway like the state file
+    pub fn audit_log(&self) -> String {
+        self.audit_log
+            .clone()
+            .unwrap_or_else(|| format!("{}-audit.jsonl", self.gateway_id))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Check an audit log's hash chain and print its head hash
+    VerifyAudit {
+        /// Audit log to check
+        path: String,
+    },
+}
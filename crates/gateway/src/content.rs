@@ -0,0 +1,230 @@
+//! Result content validation
+//!
+//! Decoding already guarantees `content` has the right shape for its
+//! `message_type`; this checks what the schema can't: non-empty text, size
+//! limits and artifact link URLs.
+
+use crate::mic_grant::ValidationError;
+use common::message::{ResultContent, ResultPayload};
+
+/// Configured content limits
+#[derive(Debug, Clone)]
+pub struct ContentLimits {
+    /// Longest accepted text field, in characters
+    pub max_text_len: usize,
+    /// Most bullets accepted in a finding
+    pub max_bullets: usize,
+    /// URL schemes accepted in artifact links, lowercase
+    pub allowed_url_schemes: Vec<String>,
+}
+
+/// Validate a result payload's content against its `message_type`
+pub fn validate_content(
+    payload: &ResultPayload,
+    limits: &ContentLimits,
+) -> Result<(), ValidationError> {
+    if payload.content.message_type() != payload.message_type {
+        return Err(ValidationError::ContentTypeMismatch);
+    }
+
+    let text = |field: &str, value: &str| check_text(field, value, limits);
+    match &payload.content {
+        ResultContent::Ack(content) => text("text", &content.text),
+        ResultContent::ClarifyingQuestion(content) => text("question", &content.question),
+        ResultContent::Progress(content) => text("text", &content.text),
+        ResultContent::Finding(content) => {
+            let bullets = content.bullets.as_deref().unwrap_or_default();
+            if bullets.len() > limits.max_bullets {
+                return Err(ValidationError::ContentTooLarge {
+                    field: "bullets".to_string(),
+                    len: bullets.len(),
+                    max: limits.max_bullets,
+                });
+            }
+            for (i, bullet) in bullets.iter().enumerate() {
+                text(&format!("bullets[{}]", i), bullet)?;
+            }
+            match &content.text {
+                Some(value) => text("text", value),
+                None if bullets.is_empty() => Err(ValidationError::EmptyContent {
+                    field: "bullets".to_string(),
+                }),
+                None => Ok(()),
+            }
+        }
+        ResultContent::Risk(content) => {
+            text("text", &content.text)?;
+            [
+                ("severity", &content.severity),
+                ("mitigation", &content.mitigation),
+            ]
+            .into_iter()
+            .filter_map(|(field, value)| Some((field, value.as_deref()?)))
+            .try_for_each(|(field, value)| check_len(field, value, limits))
+        }
+        ResultContent::Result(content) => text("text", &content.text),
+        ResultContent::ArtifactLink(content) => {
+            text("label", &content.label)?;
+            check_len("url", &content.url, limits)?;
+            check_url(&content.url, limits)
+        }
+    }
+}
+
+/// Required text: non-blank and within the length limit
+fn check_text(field: &str, value: &str, limits: &ContentLimits) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(ValidationError::EmptyContent {
+            field: field.to_string(),
+        });
+    }
+    check_len(field, value, limits)
+}
+
+fn check_len(field: &str, value: &str, limits: &ContentLimits) -> Result<(), ValidationError> {
+    let len = value.chars().count();
+    if len > limits.max_text_len {
+        return Err(ValidationError::ContentTooLarge {
+            field: field.to_string(),
+            len,
+            max: limits.max_text_len,
+        });
+    }
+    Ok(())
+}
+
+/// Require `scheme://host...` with an allowlisted scheme
+fn check_url(url: &str, limits: &ContentLimits) -> Result<(), ValidationError> {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return Err(ValidationError::InvalidUrl);
+    };
+    let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    if !valid_scheme || host.is_empty() || url.chars().any(char::is_whitespace) {
+        return Err(ValidationError::InvalidUrl);
+    }
+
+    let scheme = scheme.to_ascii_lowercase();
+    if !limits.allowed_url_schemes.contains(&scheme) {
+        return Err(ValidationError::UrlSchemeNotAllowed { scheme });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::message::{
+        AckContent, ArtifactLinkContent, FindingContent, ResultMessageType, ResultOutcome,
+    };
+
+    fn limits() -> ContentLimits {
+        ContentLimits {
+            max_text_len: 10,
+            max_bullets: 2,
+            allowed_url_schemes: vec!["https".to_string()],
+        }
+    }
+
+    fn check(content: ResultContent) -> Result<(), ValidationError> {
+        validate_content(&ResultPayload::new("task_1", content), &limits())
+    }
+
+    fn reason(result: Result<(), ValidationError>) -> &'static str {
+        result.err().map_or("ok", |e| e.reason())
+    }
+
+    fn link(url: &str) -> ResultContent {
+        ResultContent::ArtifactLink(ArtifactLinkContent {
+            label: "report".to_string(),
+            url: url.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_text_length_limit() {
+        let result = |text: &str| {
+            ResultContent::Result(ResultOutcome {
+                text: text.to_string(),
+            })
+        };
+        assert_eq!(reason(check(result("0123456789"))), "ok");
+        // Counted in characters, not bytes
+        assert_eq!(reason(check(result("éééééééééé"))), "ok");
+        assert!(matches!(
+            check(result("0123456789a")),
+            Err(ValidationError::ContentTooLarge {
+                len: 11,
+                max: 10,
+                ..
+            })
+        ));
+        assert_eq!(reason(check(result("   "))), "empty_content");
+    }
+
+    #[test]
+    fn test_bullet_limits() {
+        let finding = |bullets: &[&str], text: Option<&str>| {
+            ResultContent::Finding(FindingContent {
+                bullets: Some(bullets.iter().map(|b| b.to_string()).collect()),
+                text: text.map(str::to_string),
+            })
+        };
+        assert_eq!(reason(check(finding(&["a", "b"], None))), "ok");
+        assert!(matches!(
+            check(finding(&["a", "b", "c"], None)),
+            Err(ValidationError::ContentTooLarge { field, len: 3, max: 2 }) if field == "bullets"
+        ));
+        assert!(matches!(
+            check(finding(&["a", "0123456789a"], None)),
+            Err(ValidationError::ContentTooLarge { field, .. }) if field == "bullets[1]"
+        ));
+        assert_eq!(reason(check(finding(&[], None))), "empty_content");
+        assert_eq!(reason(check(finding(&[], Some("text")))), "ok");
+    }
+
+    #[test]
+    fn test_url_schemes() {
+        let limits = ContentLimits {
+            max_text_len: 100,
+            ..limits()
+        };
+        let check = |url: &str| {
+            reason(validate_content(
+                &ResultPayload::new("task_1", link(url)),
+                &limits,
+            ))
+        };
+        assert_eq!(check("https://example.com/report?id=1"), "ok");
+        assert_eq!(check("HTTPS://example.com"), "ok");
+        assert_eq!(check("http://example.com"), "url_scheme_not_allowed");
+        assert_eq!(check("javascript://example.com"), "url_scheme_not_allowed");
+        for invalid in [
+            "example.com",
+            "https://",
+            "https:///path",
+            "https://exa mple.com",
+            "1http://example.com",
+        ] {
+            assert_eq!(check(invalid), "invalid_url", "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_content_must_match_message_type() {
+        let mut payload = ResultPayload::new(
+            "task_1",
+            ResultContent::Ack(AckContent {
+                text: "ok".to_string(),
+            }),
+        );
+        payload.message_type = ResultMessageType::Result;
+        assert_eq!(
+            reason(validate_content(&payload, &limits())),
+            "content_type_mismatch"
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Sender identity binding
+//!
+//! Over mTLS the broker knows which certificate published a message, but the
+//! envelope's `from.id` is whatever the publisher wrote. When the broker adds
+//! the client's identity (its certificate CN) to each delivery as an MQTT v5
+//! user property, overwriting any value the client set itself, the gateway
+//! only accepts envelopes whose `from.id` matches it.
+//!
+//! Independently of the broker, envelopes can carry an Ed25519 signature by
+//! their sender, checked against the gateway's key registry.
+
+use crate::mic_grant::ValidationError;
+use crate::pool::GATEWAY_ID_PREFIX;
+use common::{Envelope, KeyRegistry, SenderKind};
+
+/// Require the envelope to be sent as the client that published it
+pub fn check_sender(envelope: &Envelope, client: Option<&str>) -> Result<(), ValidationError> {
+    if client == Some(envelope.from.id.as_str()) {
+        return Ok(());
+    }
+    Err(ValidationError::SenderSpoofed {
+        claimed: envelope.from.id.clone(),
+        client: client.map(str::to_string),
+    })
+}
+
+/// Require a mic grant or revoke to come from one of `issuers`
+///
+/// Users never issue grants, whatever their ID.
+pub fn check_issuer(envelope: &Envelope, issuers: &[String]) -> Result<(), ValidationError> {
+    if envelope.from.kind != SenderKind::User && issuers.contains(&envelope.from.id) {
+        return Ok(());
+    }
+    Err(ValidationError::UnauthorizedIssuer {
+        issuer: envelope.from.id.clone(),
+    })
+}
+
+/// Whether a forwarded candidate was published by a pooled gateway
+///
+/// Forwarding republishes the agent's envelope unchanged, so its `from.id`
+/// is the agent's; the forwarding member checked that before forwarding.
+pub fn is_gateway(client: Option<&str>) -> bool {
+    client.is_some_and(|client| client.starts_with(GATEWAY_ID_PREFIX))
+}
+
+/// Require a valid signature by `from.id`, if signatures are required
+pub fn check_signature(
+    keys: Option<&KeyRegistry>,
+    envelope: &Envelope,
+) -> Result<(), ValidationError> {
+    match keys {
+        Some(keys) => keys
+            .verify(envelope)
+            .map_err(ValidationError::InvalidSignature),
+        None => Ok(()),
+    }
+}
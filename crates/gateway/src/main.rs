@@ -5,260 +5,1181 @@
 //! - Enforce mic grants
 //! - Enforce rate limits and schemas
 //! - Republish approved messages
+//! - Hold high-risk messages for a human moderator
 //!
 //! Key property: No AI, fully deterministic, enforceable via ACLs
 
+mod audit;
 mod config;
+mod content;
+mod identity;
+mod metrics;
 mod mic_grant;
+mod moderation;
+mod policy;
+mod pool;
+mod rate_limit;
+mod state;
 mod validator;
 
+use audit::{AuditDecision, AuditLog, Decision};
 use clap::Parser;
-use common::message::HeartbeatPayload;
-use common::{topics, Envelope, EnvelopeType, RejectPayload, Sender, SenderKind};
-use config::GatewayConfig;
-use mic_grant::MicGrantTracker;
-use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
-use std::time::{SystemTime, UNIX_EPOCH};
-use tracing::{error, info, warn};
+use common::runtime::{Component, Context, Identity, MqttSettings, Runtime};
+use common::time::now_secs;
+use common::{
+    topics, AorError, Envelope, KeyRegistry, MicExpiredPayload, MicGrantSnapshotPayload,
+    ModerationDecision, ModerationRequestPayload, Payload, Redaction, RedactionPayload,
+    RejectPayload, ResultMessageType, Sender, SenderKind, Signer, Topic,
+};
+use config::{Command, GatewayConfig};
+use content::ContentLimits;
+use metrics::Metrics;
+use mic_grant::{MicGrantTracker, ValidationError};
+use moderation::{Held, HoldRules, ModerationQueue};
+use policy::Policy;
+use pool::{GatewayPool, GATEWAY_ID_PREFIX};
+use rate_limit::{RateLimiter, RateLimits};
+use state::GrantStore;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+use validator::Deadline;
+
+/// How often expired and exhausted mic grants are cleared out
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
 
-    info!("Gateway starting...");
-
     let config = GatewayConfig::parse();
+    if let Some(Command::VerifyAudit { path }) = &config.command {
+        let verified = audit::verify(path)?;
+        println!(
+            "{}: {} record(s) intact, head {}",
+            path, verified.records, verified.head
+        );
+        return Ok(());
+    }
+
+    info!("Gateway starting...");
+    if !config.gateway_id.starts_with(GATEWAY_ID_PREFIX) {
+        return Err(format!(
+            "gateway ID {} must start with \"{}\"",
+            config.gateway_id, GATEWAY_ID_PREFIX
+        )
+        .into());
+    }
 
     info!("Configuration loaded:");
     info!("  MQTT: {}:{}", config.mqtt_host, config.mqtt_port);
     info!("  Room ID: {}", config.room_id);
+    info!("  All rooms: {}", config.all_rooms);
+    info!("  Gateway ID: {}", config.gateway_id);
+    if let Some(group) = &config.share_group {
+        info!("  Share group: {}", group);
+    }
+    info!(
+        "  Agent rate limit: {}/s (burst {}), {} bytes/min",
+        config.agent_messages_per_sec, config.agent_message_burst, config.agent_bytes_per_min
+    );
+    info!(
+        "  Room rate limit: {}/s (burst {}), {} bytes/min",
+        config.room_messages_per_sec, config.room_message_burst, config.room_bytes_per_min
+    );
+    info!(
+        "  Content limits: {} chars, {} bullets, URL schemes {}",
+        config.max_text_len,
+        config.max_bullets,
+        config.allowed_url_schemes.join(",")
+    );
+    let policy = match &config.policy_file {
+        Some(path) => {
+            let policy = Policy::load(path)?;
+            info!("  Policy: {} rule(s) from {}", policy.len(), path);
+            policy
+        }
+        None => Policy::default(),
+    };
+    let hold_rules = HoldRules {
+        message_types: config.hold_message_types.clone(),
+        risk_severities: config.hold_risk_severities.clone(),
+        agents: config.hold_agents.clone(),
+    };
+    if let Some(unknown) = hold_rules.message_types.iter().find(|t| {
+        !ResultMessageType::ALL
+            .iter()
+            .any(|known| known.to_string() == **t)
+    }) {
+        return Err(format!("unknown result message type to hold: {}", unknown).into());
+    }
+    if !hold_rules.is_empty() {
+        if config.moderators.is_empty() {
+            return Err("holding messages for moderation requires --moderators".into());
+        }
+        info!(
+            "  Moderation: holding types [{}], risk severities [{}], agents [{}] for {}s",
+            hold_rules.message_types.join(","),
+            hold_rules.risk_severities.join(","),
+            hold_rules.agents.join(","),
+            config.moderation_timeout_secs
+        );
+        info!("  Moderators: {}", config.moderators.join(","));
+    }
+    info!("  Mic issuers: {}", config.mic_issuers.join(","));
+    match &config.identity_property {
+        Some(property) => info!("  Sender identity: user property {}", property),
+        None => warn!("  Sender identity: not checked, from.id is trusted"),
+    }
+    let metrics = Metrics::default();
+    if let Some(addr) = config.metrics_addr {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("  Metrics: http://{}/metrics", addr);
+        tokio::spawn(metrics::serve(listener, metrics.clone()));
+    }
+    let keys = match &config.key_registry {
+        Some(path) => {
+            let keys = KeyRegistry::load(path)?;
+            info!("  Key registry: {} key(s) from {}", keys.len(), path);
+            Some(keys)
+        }
+        None => None,
+    };
     info!("  Max validation time: {}ms", config.max_validation_time_ms);
     info!("  Verbose rejections: {}", config.verbose_rejections);
+    info!("  State file: {}", config.state_file());
+    let audit = AuditLog::open(config.audit_log())?;
+    info!("  Audit log: {}", config.audit_log());
 
-    // Initialize MQTT client
-    let mut mqttoptions = MqttOptions::new(
-        format!("{}-gateway", config.mqtt_client_id_prefix),
-        &config.mqtt_host,
-        config.mqtt_port,
+    let mut identity = Identity::new(
+        topics::room_filter(&config.room_id, config.all_rooms),
+        Sender::system(&config.gateway_id),
+        "Gateway - validates and moderates agent messages",
     );
-    mqttoptions.set_keep_alive(std::time::Duration::from_secs(config.mqtt_keep_alive_secs));
-
-    let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
+    if let Some(path) = &config.signing_key {
+        let signer = Signer::load(config.gateway_id.as_str(), path)?;
+        info!(
+            "  Signing key: {} (public key {})",
+            path,
+            signer.public_key()
+        );
+        identity = identity.signed_with(signer);
+    }
+    let mqtt = MqttSettings {
+        host: config.mqtt_host.clone(),
+        port: config.mqtt_port,
+        client_id: format!("{}-{}", config.mqtt_client_id_prefix, config.gateway_id),
+        keep_alive_secs: config.mqtt_keep_alive_secs,
+    };
 
-    // Subscribe to topics
-    let public_candidates = topics::public_candidates(&config.room_id);
-    let control = topics::control(&config.room_id);
+    info!("Gateway running");
 
-    client
-        .subscribe(&public_candidates, QoS::AtLeastOnce)
+    Runtime::new(identity, mqtt)
+        .run(Gateway::new(
+            config, policy, hold_rules, keys, metrics, audit,
+        ))
         .await?;
-    client.subscribe(&control, QoS::AtLeastOnce).await?;
-
-    info!("Subscribed to:");
-    info!("  {}", public_candidates);
-    info!("  {}", control);
-
-    // Initialize mic grant tracker
-    let mut tracker = MicGrantTracker::new();
-
-    // Spawn heartbeat task
-    let client_clone = client.clone();
-    let room_id = config.room_id.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(10));
-        let mut counter = 0u64;
-        loop {
-            interval.tick().await;
-            counter += 1;
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-
-            let payload = if counter % 3 == 0 {
-                HeartbeatPayload {
-                    ts: now,
-                    description: Some(
-                        "Gateway - validates and moderates agent messages".to_string(),
-                    ),
-                    can_accept_tasks: false,
-                }
-            } else {
-                HeartbeatPayload {
-                    ts: now,
-                    description: None,
-                    can_accept_tasks: false,
-                }
-            };
+    Ok(())
+}
 
-            let heartbeat = Envelope {
-                id: format!("gateway_heartbeat_{}", counter),
-                message_type: EnvelopeType::Heartbeat,
-                room_id: room_id.clone(),
-                from: Sender {
-                    kind: SenderKind::System,
-                    id: "gateway".to_string(),
-                },
-                ts: now,
-                payload: serde_json::to_value(payload).unwrap(),
-            };
-            let topic = format!("rooms/{}/agents/gateway/heartbeat", room_id);
-            let _ = client_clone
-                .publish(
-                    topic,
-                    QoS::AtLeastOnce,
-                    false,
-                    serde_json::to_vec(&heartbeat).unwrap(),
-                )
-                .await;
+struct Gateway {
+    config: GatewayConfig,
+    /// Mic grants per room, so grants never apply across rooms
+    trackers: HashMap<String, MicGrantTracker>,
+    /// Candidate rate limits per room
+    limiters: HashMap<String, RateLimiter>,
+    limits: RateLimits,
+    content_limits: ContentLimits,
+    policy: Policy,
+    hold_rules: HoldRules,
+    /// Candidates held for a moderator
+    moderation: ModerationQueue,
+    store: GrantStore,
+    /// Other gateways sharing candidates, when running in a share group
+    pool: Option<GatewayPool>,
+    /// Senders' public keys, when signatures are required
+    keys: Option<KeyRegistry>,
+    metrics: Metrics,
+    audit: AuditLog,
+}
+
+impl Gateway {
+    fn new(
+        config: GatewayConfig,
+        policy: Policy,
+        hold_rules: HoldRules,
+        keys: Option<KeyRegistry>,
+        metrics: Metrics,
+        audit: AuditLog,
+    ) -> Self {
+        let store = GrantStore::new(config.state_file());
+        let pool = config
+            .share_group
+            .as_ref()
+            .map(|_| GatewayPool::new(config.gateway_id.as_str()));
+        let gateway = Self {
+            trackers: store.load(now_secs()),
+            limiters: HashMap::new(),
+            limits: RateLimits {
+                agent_messages_per_sec: config.agent_messages_per_sec,
+                agent_message_burst: config.agent_message_burst,
+                agent_bytes_per_min: config.agent_bytes_per_min,
+                room_messages_per_sec: config.room_messages_per_sec,
+                room_message_burst: config.room_message_burst,
+                room_bytes_per_min: config.room_bytes_per_min,
+            },
+            content_limits: ContentLimits {
+                max_text_len: config.max_text_len,
+                max_bullets: config.max_bullets,
+                allowed_url_schemes: config
+                    .allowed_url_schemes
+                    .iter()
+                    .map(|scheme| scheme.trim().to_ascii_lowercase())
+                    .collect(),
+            },
+            policy,
+            hold_rules,
+            moderation: ModerationQueue::default(),
+            store,
+            pool,
+            keys,
+            metrics,
+            audit,
+            config,
+        };
+        gateway.count_grants();
+        gateway
+    }
+
+    /// Save grant state after a change, logging rather than failing
+    fn persist(&self) {
+        self.count_grants();
+        if let Err(e) = self.store.save(&self.trackers) {
+            error!("Failed to save mic grant state: {}", e);
         }
-    });
+    }
 
-    info!("Gateway running");
+    /// Update the active grant counts reported in metrics
+    fn count_grants(&self) {
+        self.metrics.set_active_grants(
+            self.trackers
+                .iter()
+                .map(|(room_id, tracker)| (room_id, tracker.len())),
+        );
+    }
 
-    // Event loop
-    loop {
-        match eventloop.poll().await {
-            Ok(notification) => {
-                if let Event::Incoming(Packet::Publish(p)) = notification {
-                    let topic = p.topic.clone();
-                    let payload = p.payload.to_vec();
-
-                    // Parse envelope
-                    let envelope: Envelope = match serde_json::from_slice(&payload) {
-                        Ok(e) => e,
-                        Err(e) => {
-                            warn!("Failed to parse envelope from {}: {}", topic, e);
-                            continue;
-                        }
-                    };
+    /// Whether this gateway counts the grant's messages, and so speaks for it
+    fn owns(&self, room_id: &str, agent_id: &str, task_id: &str, current_ts: u64) -> bool {
+        self.pool
+            .as_ref()
+            .is_none_or(|pool| pool.owner(room_id, agent_id, task_id, current_ts) == pool.self_id())
+    }
 
-                    // Handle message based on topic
-                    if topic == control {
-                        handle_control_message(&envelope, &mut tracker);
-                    } else if topic == public_candidates {
-                        handle_candidate_message(&envelope, &mut tracker, &client, &config).await;
-                    }
-                }
+    /// Drop the room's expired and exhausted grants and announce each on control
+    ///
+    /// In a pool every member drops the grant, but only its owner announces it.
+    /// Returns whether any grant was removed.
+    async fn sweep_room(&mut self, ctx: &Context, room_id: &str) -> bool {
+        let now = now_secs();
+        let Some(tracker) = self.trackers.get_mut(room_id) else {
+            return false;
+        };
+        let ended = tracker.sweep(now);
+
+        for (state, reason) in &ended {
+            let grant = &state.grant;
+            if !self.owns(room_id, &grant.agent_id, &grant.task_id, now) {
+                continue;
             }
-            Err(e) => {
-                error!("MQTT error: {}", e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+            info!(
+                "Mic grant {}: agent={}, task={}",
+                reason, grant.agent_id, grant.task_id
+            );
+            let notice = ctx.envelopes_in(room_id).mic_expired(MicExpiredPayload {
+                task_id: grant.task_id.clone(),
+                agent_id: grant.agent_id.clone(),
+                reason: *reason,
+            });
+            if let Err(e) = ctx.publish(topics::control(room_id), &notice).await {
+                error!("Failed to publish mic expiry: {}", e);
             }
         }
+        !ended.is_empty()
     }
-}
 
-fn handle_control_message(envelope: &Envelope, tracker: &mut MicGrantTracker) {
-    match envelope.message_type {
-        EnvelopeType::MicGrant => {
-            if let Ok(payload) =
-                serde_json::from_value::<common::MicGrantPayload>(envelope.payload.clone())
-            {
-                info!(
-                    "Mic grant: agent={}, task={}, max_messages={}",
-                    payload.agent_id, payload.task_id, payload.max_messages
-                );
-                tracker.grant(payload);
-            } else {
-                warn!("Failed to parse MicGrant payload");
+    /// Publish the room's grants as the retained snapshot late joiners start from
+    async fn publish_snapshot(&self, ctx: &Context, room_id: &str) {
+        let grants = self
+            .trackers
+            .get(room_id)
+            .map(|tracker| tracker.grants().cloned().collect())
+            .unwrap_or_default();
+        let snapshot = ctx
+            .envelopes_in(room_id)
+            .mic_grant_snapshot(MicGrantSnapshotPayload {
+                grants,
+                generated_at: now_secs(),
+            });
+        if let Err(e) = ctx
+            .publish_retained(topics::mic_grants(room_id), &snapshot)
+            .await
+        {
+            error!("Failed to publish mic grant snapshot: {}", e);
+        }
+    }
+
+    /// Validate, filter and then republish, hold or reject a candidate
+    ///
+    /// Returns whether it passed validation, and so counted against its grant.
+    async fn handle_candidate(
+        &mut self,
+        ctx: &Context,
+        room_id: &str,
+        mut envelope: Envelope,
+    ) -> bool {
+        let current_ts = now_secs();
+        let started = Instant::now();
+        let deadline = Deadline::start(self.config.max_validation_time_ms);
+        let tracker = self.trackers.entry(room_id.to_string()).or_default();
+        let limiter = self
+            .limiters
+            .entry(room_id.to_string())
+            .or_insert_with(|| RateLimiter::new(self.limits));
+
+        // Check the signature and validate the message, then apply content
+        // policy to what passed, all within the time budget
+        let checked = identity::check_signature(self.keys.as_ref(), &envelope)
+            .and_then(|()| {
+                validator::validate_message(
+                    &envelope,
+                    room_id,
+                    tracker,
+                    limiter,
+                    &self.content_limits,
+                    &deadline,
+                    current_ts,
+                )
+            })
+            .and_then(|()| self.policy.apply(&mut envelope, &deadline))
+            .and_then(|redactions| deadline.check().map(|()| redactions));
+        self.metrics.validated(started.elapsed(), checked.is_ok());
+        let redactions = match checked {
+            Ok(redactions) => redactions,
+            Err(e) => {
+                self.reject(ctx, room_id, &envelope, &e).await;
+                return false;
             }
+        };
+        // Counted only once nothing can reject it, so rejections never use
+        // up grant state that isn't persisted
+        if let Payload::Result(result) = &envelope.payload {
+            tracker.count_message(&envelope.from.id, &result.task_id, &self.config.gateway_id);
         }
-        EnvelopeType::MicRevoke => {
-            if let Ok(payload) =
-                serde_json::from_value::<common::MicRevokePayload>(envelope.payload.clone())
-            {
+
+        match self.hold_rules.matches(&envelope) {
+            Some(reason) => {
+                let expires_at = current_ts + self.config.moderation_timeout_secs;
                 info!(
-                    "Mic revoke: agent={}, task={}",
-                    payload.agent_id, payload.task_id
+                    "Holding message {} from {} for moderation ({})",
+                    envelope.id, envelope.from.id, reason
                 );
-                tracker.revoke(&payload.agent_id, &payload.task_id);
-            } else {
-                warn!("Failed to parse MicRevoke payload");
+                let request = ctx
+                    .envelopes_in(room_id)
+                    .caused_by(&envelope)
+                    .moderation_request(ModerationRequestPayload {
+                        reason: reason.clone(),
+                        expires_at,
+                        message: Box::new(envelope.clone()),
+                    });
+                if let Err(e) = ctx.publish(topics::moderation(room_id), &request).await {
+                    error!("Failed to publish moderation request: {}", e);
+                }
+                self.metrics.held(room_id, &envelope);
+                self.record(decision(
+                    room_id,
+                    &envelope,
+                    AuditDecision::Held,
+                    Some(reason),
+                    None,
+                ));
+                self.moderation.hold(Held {
+                    room_id: room_id.to_string(),
+                    envelope,
+                    redactions,
+                    expires_at,
+                });
+            }
+            None => {
+                self.approve(ctx, room_id, &envelope, redactions, None)
+                    .await
             }
         }
-        _ => {
-            // Ignore other control messages
+        true
+    }
+
+    /// Reject a refused mic grant or revoke, once per pool
+    async fn reject_control(
+        &mut self,
+        ctx: &Context,
+        room_id: &str,
+        envelope: &Envelope,
+        e: &ValidationError,
+    ) {
+        let (agent_id, task_id) = match &envelope.payload {
+            Payload::MicGrant(grant) => (&grant.agent_id, &grant.task_id),
+            Payload::MicRevoke(revoke) => (&revoke.agent_id, &revoke.task_id),
+            _ => return,
+        };
+        if self.owns(room_id, agent_id, task_id, now_secs()) {
+            self.reject(ctx, room_id, envelope, e).await;
+        } else {
+            debug!("Refused control message {}: {}", envelope.id, e);
         }
     }
-}
 
-async fn handle_candidate_message(
-    envelope: &Envelope,
-    tracker: &mut MicGrantTracker,
-    client: &AsyncClient,
-    _config: &GatewayConfig,
-) {
-    let current_ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-
-    // Validate the message
-    match validator::validate_message(envelope, tracker, current_ts) {
-        Ok(()) => {
-            // Republish to public topic
-            let public_topic = topics::public(&envelope.room_id);
-            let payload = serde_json::to_vec(&envelope).unwrap();
-
-            if let Err(e) = client
-                .publish(public_topic.clone(), QoS::AtLeastOnce, false, payload)
+    /// Republish an approved candidate and record the approval
+    ///
+    /// `approved_by` names the moderator who released a held candidate.
+    async fn approve(
+        &mut self,
+        ctx: &Context,
+        room_id: &str,
+        envelope: &Envelope,
+        redactions: Vec<Redaction>,
+        approved_by: Option<String>,
+    ) {
+        publish_approved(ctx, room_id, envelope, redactions).await;
+        self.metrics.approved(room_id, envelope);
+        self.record(decision(
+            room_id,
+            envelope,
+            AuditDecision::Approved,
+            approved_by,
+            None,
+        ));
+    }
+
+    /// Reject a candidate or control message on control and record the rejection
+    async fn reject(
+        &mut self,
+        ctx: &Context,
+        room_id: &str,
+        envelope: &Envelope,
+        e: &ValidationError,
+    ) {
+        reject_message(ctx, room_id, envelope, e, self.config.verbose_rejections).await;
+        // Every path here checked the sender first, if configured
+        let bound = self.config.identity_property.is_some()
+            && !matches!(e, ValidationError::SenderSpoofed { .. });
+        let known = bound || self.has_grant(room_id, &envelope.from.id);
+        self.metrics.rejected(room_id, envelope, e.reason(), known);
+        self.record(decision(
+            room_id,
+            envelope,
+            AuditDecision::Rejected,
+            Some(e.reason().to_string()),
+            e.detail(),
+        ));
+    }
+
+    /// Reject a candidate whose envelope uses an unsupported protocol major version
+    ///
+    /// The envelope can't be decoded, so the message, task and thread IDs are read
+    /// from the raw JSON on a best-effort basis.
+    async fn reject_unsupported_version(
+        &mut self,
+        ctx: &Context,
+        room_id: &str,
+        payload: &[u8],
+        version: String,
+    ) {
+        let raw: serde_json::Value = serde_json::from_slice(payload).unwrap_or_default();
+        let field = |value: &serde_json::Value| value.as_str().unwrap_or_default().to_string();
+
+        let message_id = field(&raw["id"]);
+        let thread_id = match raw["correlation_id"].as_str() {
+            Some(correlation_id) => correlation_id.to_string(),
+            None => message_id.clone(),
+        };
+        let agent_id = field(&raw["from"]["id"]);
+        let task_id = field(&raw["payload"]["task_id"]);
+
+        let e = ValidationError::UnsupportedProtocolVersion(version);
+        let message_type = raw["payload"]["message_type"]
+            .as_str()
+            .or(raw["type"].as_str())
+            .unwrap_or_default();
+        let bound = self
+            .config
+            .identity_property
+            .as_ref()
+            .is_some_and(|property| ctx.user_property(property) == Some(agent_id.as_str()));
+        let known = bound || self.has_grant(room_id, &agent_id);
+        self.metrics.rejected_raw(
+            room_id,
+            Some(agent_id.as_str()).filter(|_| known),
+            message_type,
+            e.reason(),
+        );
+        self.record(Decision {
+            room_id: room_id.to_string(),
+            message_id: message_id.clone(),
+            agent_id,
+            task_id: task_id.clone(),
+            decision: AuditDecision::Rejected,
+            reason: Some(e.reason().to_string()),
+            detail: e.detail(),
+            grant: None,
+        });
+
+        let reject_payload = RejectPayload {
+            message_id: message_id.clone(),
+            task_id,
+            reason: e.reason().to_string(),
+            detail: e.detail().filter(|_| self.config.verbose_rejections),
+        };
+        let reject_envelope = ctx
+            .envelopes_in(room_id)
+            .linked(thread_id, message_id)
+            .reject(reject_payload);
+        publish_rejection(ctx, room_id, &reject_envelope).await;
+    }
+
+    /// Append a decision to the audit log with the grant it was made under
+    fn record(&mut self, mut decision: Decision) {
+        decision.grant = self
+            .trackers
+            .get(&decision.room_id)
+            .and_then(|tracker| tracker.get(&decision.agent_id, &decision.task_id))
+            .cloned();
+        if let Err(e) = self.audit.append(decision, now_secs()) {
+            error!(
+                "Failed to write audit log {}: {}",
+                self.audit.path().display(),
+                e
+            );
+        }
+    }
+
+    /// Release or reject a held candidate on a moderator's decision
+    async fn handle_moderation(&mut self, ctx: &Context, room_id: &str, envelope: &Envelope) {
+        let Payload::ModerationDecision(decision) = &envelope.payload else {
+            return;
+        };
+        if let Err(e) = self
+            .check_sender(ctx, envelope)
+            .and_then(|()| identity::check_signature(self.keys.as_ref(), envelope))
+        {
+            warn!(
+                "Ignoring moderation decision on {}: {}",
+                decision.message_id, e
+            );
+            return;
+        }
+        if envelope.from.kind != SenderKind::User
+            || !self.config.moderators.contains(&envelope.from.id)
+        {
+            warn!(
+                "Ignoring moderation decision on {} from non-moderator {}",
+                decision.message_id, envelope.from.id
+            );
+            return;
+        }
+        let Some(held) = self.moderation.take(room_id, &decision.message_id) else {
+            // Unknown, already decided, or held by another pool member
+            debug!(
+                "No held message {} in room {}",
+                decision.message_id, room_id
+            );
+            return;
+        };
+
+        let verdict = match decision.decision {
+            ModerationDecision::Approve => "approved",
+            ModerationDecision::Deny => "denied",
+        };
+        info!(
+            "Moderator {} {} message {}{}",
+            envelope.from.id,
+            verdict,
+            decision.message_id,
+            decision
+                .reason
+                .as_ref()
+                .map(|r| format!(": {}", r))
+                .unwrap_or_default()
+        );
+        match decision.decision {
+            ModerationDecision::Approve => {
+                let moderator = format!("moderator:{}", envelope.from.id);
+                self.approve(
+                    ctx,
+                    room_id,
+                    &held.envelope,
+                    held.redactions,
+                    Some(moderator),
+                )
                 .await
-            {
-                error!("Failed to publish to {}: {}", public_topic, e);
-            } else {
-                info!(
-                    "Approved message {} from {} to public",
-                    envelope.id, envelope.from.id
-                );
             }
+            ModerationDecision::Deny => {
+                let denied = ValidationError::ModeratorDenied {
+                    reason: decision.reason.clone(),
+                };
+                self.reject(ctx, room_id, &held.envelope, &denied).await
+            }
+        }
+    }
+
+    /// Check `from.id` against the publishing client, if sender identity is checked
+    fn check_sender(&self, ctx: &Context, envelope: &Envelope) -> Result<(), ValidationError> {
+        match &self.config.identity_property {
+            Some(property) => identity::check_sender(envelope, ctx.user_property(property)),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether `agent_id` holds a mic grant in the room, so may be named in
+    /// metrics even when its identity isn't bound
+    fn has_grant(&self, room_id: &str, agent_id: &str) -> bool {
+        self.trackers.get(room_id).is_some_and(|tracker| {
+            tracker
+                .grants()
+                .any(|state| state.grant.agent_id == agent_id)
+        })
+    }
+
+    /// Whether a forwarded candidate came from a pooled gateway, if sender
+    /// identity is checked
+    fn forwarded_by_gateway(&self, ctx: &Context) -> bool {
+        match &self.config.identity_property {
+            Some(property) => identity::is_gateway(ctx.user_property(property)),
+            None => true,
+        }
+    }
+
+    fn rooms(&self) -> &str {
+        topics::room_filter(&self.config.room_id, self.config.all_rooms)
+    }
+}
+
+impl Component for Gateway {
+    fn subscriptions(&self) -> Vec<String> {
+        let candidates = topics::public_candidates(self.rooms());
+        let mut subscriptions = vec![
+            topics::control(self.rooms()),
+            topics::mic_grants(self.rooms()),
+        ];
+        if !self.hold_rules.is_empty() {
+            subscriptions.push(topics::moderation(self.rooms()));
+        }
+        match &self.config.share_group {
+            Some(group) => subscriptions.extend([
+                topics::shared(group, &candidates),
+                topics::gateway_candidates(self.rooms(), &self.config.gateway_id),
+                topics::all_agent_heartbeats(self.rooms()),
+            ]),
+            None => subscriptions.push(candidates),
         }
-        Err(e) => {
-            // Publish rejection
+        subscriptions
+    }
+
+    async fn on_envelope(&mut self, ctx: &Context, topic: &str, envelope: Envelope) {
+        let topic = match Topic::parse(topic) {
+            Ok(topic) => topic,
+            Err(e) => {
+                warn!("{}", e);
+                return;
+            }
+        };
+
+        // Handle message based on topic
+        let changed = match topic {
+            Topic::Control { room_id } => {
+                let client = self
+                    .config
+                    .identity_property
+                    .as_ref()
+                    .map(|property| ctx.user_property(property));
+                let tracker = self.trackers.entry(room_id.to_string()).or_default();
+                let issuers = &self.config.mic_issuers;
+                match handle_control_message(
+                    &envelope,
+                    client,
+                    issuers,
+                    self.keys.as_ref(),
+                    tracker,
+                ) {
+                    Ok(changed) => changed,
+                    Err(e) => {
+                        self.reject_control(ctx, room_id, &envelope, &e).await;
+                        false
+                    }
+                }
+            }
+            Topic::PublicCandidates { room_id } => {
+                // Checked before forwarding, since the owner only sees the forwarder
+                if let Err(e) = self.check_sender(ctx, &envelope) {
+                    self.reject(ctx, room_id, &envelope, &e).await;
+                    return;
+                }
+                match other_owner(self.pool.as_ref(), room_id, &envelope) {
+                    Some(owner) => {
+                        forward_candidate(&envelope, room_id, &owner, ctx).await;
+                        false
+                    }
+                    None => self.handle_candidate(ctx, room_id, envelope).await,
+                }
+            }
+            // Already routed here by its owner lookup, so never forwarded again
+            Topic::GatewayCandidates { room_id, .. } => {
+                if !self.forwarded_by_gateway(ctx) {
+                    warn!(
+                        "Ignoring message {} forwarded by a client that isn't a gateway",
+                        envelope.id
+                    );
+                    return;
+                }
+                self.handle_candidate(ctx, room_id, envelope).await
+            }
+            Topic::Heartbeat { agent_id, .. } => {
+                // A forged member would be handed a share of the room's grants
+                let authentic = self
+                    .check_sender(ctx, &envelope)
+                    .and_then(|()| identity::check_signature(self.keys.as_ref(), &envelope));
+                if let Some(pool) = &mut self.pool {
+                    if envelope.from.kind == SenderKind::System
+                        && envelope.from.id == agent_id
+                        && agent_id.starts_with(GATEWAY_ID_PREFIX)
+                    {
+                        match authentic {
+                            Ok(()) => pool.observe(agent_id, now_secs()),
+                            Err(e) => warn!("Ignoring heartbeat from {}: {}", agent_id, e),
+                        }
+                    }
+                }
+                false
+            }
+            Topic::MicGrants { room_id } => {
+                // Snapshots carry grants, so the publisher must be the gateway it claims
+                if let Err(e) = self.check_sender(ctx, &envelope) {
+                    warn!("Ignoring mic grant snapshot {}: {}", envelope.id, e);
+                    return;
+                }
+                // Adopted grants came from a snapshot, so there's nothing to republish
+                let tracker = self.trackers.entry(room_id.to_string()).or_default();
+                if handle_snapshot_message(
+                    &envelope,
+                    room_id,
+                    &self.config.gateway_id,
+                    self.keys.as_ref(),
+                    tracker,
+                ) {
+                    self.persist();
+                }
+                false
+            }
+            Topic::Moderation { room_id } => {
+                self.handle_moderation(ctx, room_id, &envelope).await;
+                false
+            }
+            _ => false,
+        };
+        if changed {
+            self.sweep_room(ctx, topic.room_id()).await;
+            self.persist();
+            self.publish_snapshot(ctx, topic.room_id()).await;
+        }
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        Some(SWEEP_INTERVAL)
+    }
+
+    async fn on_tick(&mut self, ctx: &Context) {
+        for held in self.moderation.expire(now_secs()) {
             warn!(
-                "Rejected message {} from {}: {}",
-                envelope.id, envelope.from.id, e
+                "No moderator decided on message {} from {} in time",
+                held.envelope.id, held.envelope.from.id
             );
+            let timeout = ValidationError::ModerationTimeout {
+                timeout_secs: self.config.moderation_timeout_secs,
+            };
+            self.reject(ctx, &held.room_id, &held.envelope, &timeout)
+                .await;
+        }
 
-            let reject_envelope = create_rejection(envelope, &e.to_string(), current_ts);
-            let control_topic = topics::control(&envelope.room_id);
-            let payload = serde_json::to_vec(&reject_envelope).unwrap();
+        let now = Instant::now();
+        self.limiters.retain(|_, limiter| !limiter.evict_idle(now));
 
-            if let Err(err) = client
-                .publish(control_topic, QoS::AtLeastOnce, false, payload)
-                .await
-            {
-                error!("Failed to publish rejection: {}", err);
+        let rooms: Vec<String> = self.trackers.keys().cloned().collect();
+        let mut changed = false;
+        for room_id in rooms {
+            if self.sweep_room(ctx, &room_id).await {
+                self.publish_snapshot(ctx, &room_id).await;
+                changed = true;
             }
         }
+        if changed {
+            self.persist();
+        }
+    }
+
+    async fn on_invalid(&mut self, ctx: &Context, topic: &str, payload: &[u8], error: AorError) {
+        match (error, Topic::parse(topic)) {
+            (AorError::UnsupportedVersion(version), Ok(Topic::PublicCandidates { room_id })) => {
+                warn!("Unsupported protocol version {} on {}", version, topic);
+                self.reject_unsupported_version(ctx, room_id, payload, version)
+                    .await;
+            }
+            (e, _) => warn!("Failed to parse envelope from {}: {}", topic, e),
+        }
+    }
+}
+
+/// Apply an authorized grant or revoke
+///
+/// `client` is the publishing client's broker-supplied identity, when sender
+/// identity is checked. Returns whether grant state changed, or why the grant
+/// or revoke was refused.
+fn handle_control_message(
+    envelope: &Envelope,
+    client: Option<Option<&str>>,
+    issuers: &[String],
+    keys: Option<&KeyRegistry>,
+    tracker: &mut MicGrantTracker,
+) -> Result<bool, ValidationError> {
+    if !matches!(
+        envelope.payload,
+        Payload::MicGrant(_) | Payload::MicRevoke(_)
+    ) {
+        // Ignore other control messages
+        return Ok(false);
+    }
+    // The issuer list only means something once `from.id` is bound
+    if let Some(client) = client {
+        identity::check_sender(envelope, client)?;
+    }
+    identity::check_issuer(envelope, issuers)?;
+    identity::check_signature(keys, envelope)?;
+
+    match &envelope.payload {
+        Payload::MicGrant(payload) => {
+            info!(
+                "Mic grant: agent={}, task={}, max_messages={}",
+                payload.agent_id, payload.task_id, payload.max_messages
+            );
+            tracker.grant(payload.clone());
+        }
+        Payload::MicRevoke(payload) => {
+            info!(
+                "Mic revoke: agent={}, task={}",
+                payload.agent_id, payload.task_id
+            );
+            tracker.revoke(&payload.agent_id, &payload.task_id, now_secs());
+        }
+        _ => unreachable!("checked above"),
+    }
+    Ok(true)
+}
+
+/// Pool member other than this one that owns the candidate's mic grant
+///
+/// An owner whose heartbeat is late may have died, so its candidates are
+/// handled here rather than forwarded into the void.
+fn other_owner(pool: Option<&GatewayPool>, room_id: &str, envelope: &Envelope) -> Option<String> {
+    let pool = pool?;
+    let Payload::Result(result) = &envelope.payload else {
+        return None;
+    };
+    let now = now_secs();
+    let owner = pool.owner(room_id, &envelope.from.id, &result.task_id, now);
+    if owner == pool.self_id() {
+        return None;
+    }
+    if !pool.is_fresh(owner, now) {
+        warn!(
+            "Owner {} of message {} missed its heartbeat; handling it here",
+            owner, envelope.id
+        );
+        return None;
     }
+    Some(owner.to_string())
 }
 
-fn create_rejection(original: &Envelope, reason: &str, ts: u64) -> Envelope {
-    let task_id = if original.message_type == EnvelopeType::Result {
-        serde_json::from_value::<common::ResultPayload>(original.payload.clone())
-            .ok()
-            .map(|r| r.task_id)
-            .unwrap_or_default()
+/// Hand a candidate to the pool member that counts its mic grant
+async fn forward_candidate(envelope: &Envelope, room_id: &str, owner: &str, ctx: &Context) {
+    let topic = topics::gateway_candidates(room_id, owner);
+    if let Err(e) = ctx.publish(topic, envelope).await {
+        error!("Failed to forward {} to {}: {}", envelope.id, owner, e);
     } else {
-        String::new()
+        debug!("Forwarded message {} to {}", envelope.id, owner);
+    }
+}
+
+/// Bootstrap from another gateway's grant snapshot; returns whether grant state changed
+///
+/// The caller binds the sender to its client identity first, if configured.
+fn handle_snapshot_message(
+    envelope: &Envelope,
+    room_id: &str,
+    self_id: &str,
+    keys: Option<&KeyRegistry>,
+    tracker: &mut MicGrantTracker,
+) -> bool {
+    let Payload::MicGrantSnapshot(snapshot) = &envelope.payload else {
+        return false;
     };
+    // The broker hands our own retained snapshot back on reconnect, by which
+    // time it may list grants that have since ended
+    if envelope.from.id == self_id {
+        return false;
+    }
+    // Snapshots carry grants, so only gateways may publish them
+    if envelope.from.kind != SenderKind::System || !envelope.from.id.starts_with(GATEWAY_ID_PREFIX)
+    {
+        warn!(
+            "Ignoring mic grant snapshot from non-gateway {}",
+            envelope.from.id
+        );
+        return false;
+    }
+    if let Err(e) = identity::check_signature(keys, envelope) {
+        warn!(
+            "Ignoring mic grant snapshot from {}: {}",
+            envelope.from.id, e
+        );
+        return false;
+    }
+    let changed = tracker.merge(snapshot.grants.iter().cloned(), now_secs());
+    if changed && keys.is_none() {
+        warn!(
+            "Adopted UNSIGNED mic grants from snapshot by {} for room {}; \
+             set --key-registry to only merge snapshots signed by a gateway",
+            envelope.from.id, room_id
+        );
+    } else if changed {
+        info!(
+            "Adopted mic grants from snapshot by {} for room {}",
+            envelope.from.id, room_id
+        );
+    }
+    changed
+}
+
+/// Republish an approved candidate and tell its sender about any redactions
+async fn publish_approved(
+    ctx: &Context,
+    room_id: &str,
+    envelope: &Envelope,
+    redactions: Vec<Redaction>,
+) {
+    let public_topic = topics::public(room_id);
 
+    if let Err(e) = ctx.publish(public_topic.clone(), envelope).await {
+        error!("Failed to publish to {}: {}", public_topic, e);
+    } else {
+        info!(
+            "Approved message {} from {} to public",
+            envelope.id, envelope.from.id
+        );
+    }
+    if !redactions.is_empty() {
+        notify_redaction(envelope, room_id, redactions, ctx).await;
+    }
+}
+
+/// Publish a rejection of a candidate or control message on control
+///
+/// Verbose rejections add which constraint failed and the counters involved.
+async fn reject_message(
+    ctx: &Context,
+    room_id: &str,
+    envelope: &Envelope,
+    e: &ValidationError,
+    verbose: bool,
+) {
+    warn!(
+        "Rejected message {} from {}: {}",
+        envelope.id, envelope.from.id, e
+    );
+
+    let task_id = match &envelope.payload {
+        Payload::Result(result) => result.task_id.clone(),
+        Payload::MicGrant(grant) => grant.task_id.clone(),
+        Payload::MicRevoke(revoke) => revoke.task_id.clone(),
+        _ => String::new(),
+    };
     let reject_payload = RejectPayload {
-        message_id: original.id.clone(),
+        message_id: envelope.id.clone(),
         task_id,
-        reason: reason.to_string(),
+        reason: e.reason().to_string(),
+        detail: e.detail().filter(|_| verbose),
+    };
+    let reject_envelope = ctx
+        .envelopes_in(room_id)
+        .caused_by(envelope)
+        .reject(reject_payload);
+    publish_rejection(ctx, room_id, &reject_envelope).await;
+}
+
+/// Tell the sender on control what was changed in its republished message
+async fn notify_redaction(
+    envelope: &Envelope,
+    room_id: &str,
+    redactions: Vec<Redaction>,
+    ctx: &Context,
+) {
+    let Payload::Result(result) = &envelope.payload else {
+        return;
     };
+    info!(
+        "Redacted message {} from {}: {}",
+        envelope.id,
+        envelope.from.id,
+        redactions
+            .iter()
+            .map(|r| format!("{} {} by {}", r.field, r.action, r.rule))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let notice = ctx
+        .envelopes_in(room_id)
+        .caused_by(envelope)
+        .redaction(RedactionPayload {
+            message_id: envelope.id.clone(),
+            task_id: result.task_id.clone(),
+            agent_id: envelope.from.id.clone(),
+            redactions,
+        });
+    if let Err(e) = ctx.publish(topics::control(room_id), &notice).await {
+        error!("Failed to publish redaction notice: {}", e);
+    }
+}
+
+/// Audit decision about `envelope`, before its grant is looked up
+///
+/// Grants and revokes are recorded against the agent and task they name.
+fn decision(
+    room_id: &str,
+    envelope: &Envelope,
+    decision: AuditDecision,
+    reason: Option<String>,
+    detail: Option<String>,
+) -> Decision {
+    let (agent_id, task_id) = match &envelope.payload {
+        Payload::Result(result) => (&envelope.from.id, &result.task_id),
+        Payload::MicGrant(grant) => (&grant.agent_id, &grant.task_id),
+        Payload::MicRevoke(revoke) => (&revoke.agent_id, &revoke.task_id),
+        _ => (&envelope.from.id, &String::new()),
+    };
+    Decision {
+        room_id: room_id.to_string(),
+        message_id: envelope.id.clone(),
+        agent_id: agent_id.clone(),
+        task_id: task_id.clone(),
+        decision,
+        reason,
+        detail,
+        grant: None,
+    }
+}
+
+async fn publish_rejection(ctx: &Context, room_id: &str, reject_envelope: &Envelope) {
+    if let Err(err) = ctx.publish(topics::control(room_id), reject_envelope).await {
+        error!("Failed to publish rejection: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::MicGrantPayload;
+
+    fn grant(from: &str) -> Envelope {
+        Envelope::builder("room_1", Sender::agent(from)).mic_grant(MicGrantPayload {
+            task_id: "task_1".to_string(),
+            agent_id: "math".to_string(),
+            max_messages: 5,
+            allowed_message_types: vec![ResultMessageType::Result],
+            expires_at: u64::MAX,
+        })
+    }
+
+    #[test]
+    fn test_control_rejects_spoofed_issuer() {
+        let issuers = vec!["facilitator".to_string()];
+        let mut tracker = MicGrantTracker::default();
+
+        // Claims to be the facilitator but was published by an agent
+        let spoofed = grant("facilitator");
+        let result =
+            handle_control_message(&spoofed, Some(Some("math")), &issuers, None, &mut tracker);
+        assert!(matches!(result, Err(ValidationError::SenderSpoofed { .. })));
+        let result = handle_control_message(&spoofed, Some(None), &issuers, None, &mut tracker);
+        assert!(matches!(result, Err(ValidationError::SenderSpoofed { .. })));
+        assert!(tracker.is_empty());
+
+        // Bound to its publisher, an agent still can't grant itself the mic
+        let result = handle_control_message(
+            &grant("math"),
+            Some(Some("math")),
+            &issuers,
+            None,
+            &mut tracker,
+        );
+        assert!(matches!(
+            result,
+            Err(ValidationError::UnauthorizedIssuer { .. })
+        ));
 
-    Envelope {
-        id: format!("reject_{}", original.id),
-        message_type: EnvelopeType::Reject,
-        room_id: original.room_id.clone(),
-        from: common::Sender {
-            kind: common::SenderKind::System,
-            id: "gateway".to_string(),
-        },
-        ts,
-        payload: serde_json::to_value(reject_payload).unwrap(),
+        let authentic = grant("facilitator");
+        let result = handle_control_message(
+            &authentic,
+            Some(Some("facilitator")),
+            &issuers,
+            None,
+            &mut tracker,
+        );
+        assert!(matches!(result, Ok(true)));
+        assert!(tracker.get("math", "task_1").is_some());
+    }
+
+    #[test]
+    fn test_snapshot_does_not_restore_revoked_grant() {
+        let issuers = vec!["facilitator".to_string()];
+        let mut tracker = MicGrantTracker::default();
+        handle_control_message(&grant("facilitator"), None, &issuers, None, &mut tracker).unwrap();
+        let snapshot = |gateway_id: &str, tracker: &MicGrantTracker| {
+            Envelope::builder("room_1", Sender::system(gateway_id)).mic_grant_snapshot(
+                MicGrantSnapshotPayload {
+                    grants: tracker.grants().cloned().collect(),
+                    generated_at: now_secs(),
+                },
+            )
+        };
+        let own = snapshot("gateway-a", &tracker);
+        let other = snapshot("gateway-b", &tracker);
+
+        let revoke = Envelope::builder("room_1", Sender::agent("facilitator")).mic_revoke(
+            common::MicRevokePayload {
+                task_id: "task_1".to_string(),
+                agent_id: "math".to_string(),
+            },
+        );
+        handle_control_message(&revoke, None, &issuers, None, &mut tracker).unwrap();
+
+        // Neither our own echoed snapshot nor a peer's older one brings it back
+        for snapshot in [own, other] {
+            assert!(!handle_snapshot_message(
+                &snapshot,
+                "room_1",
+                "gateway-a",
+                None,
+                &mut tracker
+            ));
+            assert!(tracker.get("math", "task_1").is_none());
+        }
     }
 }
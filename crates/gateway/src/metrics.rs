@@ -0,0 +1,466 @@
+//! Prometheus metrics
+//!
+//! Counts what the gateway decides about each message, per room, agent and
+//! message type, and serves them in the Prometheus text format on
+//! `GET /metrics` when `--metrics-addr` is set.
+//!
+//! `from.id` and message types are whatever the publisher wrote, so rejected
+//! messages are only labelled with them when the gateway knows the sender;
+//! the rest share [`UNKNOWN`] labels rather than growing a series each.
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use common::{Envelope, EnvelopeType, Payload, ResultMessageType};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::error;
+
+/// Label for senders and message types the gateway can't vouch for
+pub const UNKNOWN: &str = "unknown";
+
+/// Upper bounds of the validation latency buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+];
+
+/// Labels identifying where a message came from
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Source {
+    room_id: String,
+    agent_id: String,
+    message_type: String,
+}
+
+impl Source {
+    fn new(room_id: &str, agent_id: &str, message_type: &str) -> Self {
+        Self {
+            room_id: room_id.to_string(),
+            agent_id: agent_id.to_string(),
+            message_type: message_type.to_string(),
+        }
+    }
+
+    fn of(room_id: &str, envelope: &Envelope) -> Self {
+        Self::new(room_id, &envelope.from.id, &message_type(envelope))
+    }
+
+    /// Like [`of`](Self::of), with the sender hidden unless `known`
+    fn of_sender(room_id: &str, envelope: &Envelope, known: bool) -> Self {
+        let agent_id = if known { &envelope.from.id } else { UNKNOWN };
+        Self::new(room_id, agent_id, &message_type(envelope))
+    }
+
+    fn labels(&self) -> String {
+        format!(
+            "room=\"{}\",agent=\"{}\",message_type=\"{}\"",
+            escape(&self.room_id),
+            escape(&self.agent_id),
+            escape(&self.message_type)
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations at or below each of [`LATENCY_BUCKETS`]
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    approved: BTreeMap<Source, u64>,
+    /// Keyed by source and reason code
+    rejected: BTreeMap<(Source, &'static str), u64>,
+    held: BTreeMap<Source, u64>,
+    /// Keyed by whether the message passed validation
+    validation: BTreeMap<&'static str, Histogram>,
+    active_grants: BTreeMap<String, usize>,
+}
+
+/// Gateway metrics, shared with the HTTP endpoint
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Metrics {
+    pub fn approved(&self, room_id: &str, envelope: &Envelope) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .approved
+            .entry(Source::of(room_id, envelope))
+            .or_default() += 1;
+    }
+
+    /// Count a rejection, naming the sender only if it is `known`
+    pub fn rejected(&self, room_id: &str, envelope: &Envelope, reason: &'static str, known: bool) {
+        self.rejected_from(Source::of_sender(room_id, envelope, known), reason);
+    }
+
+    /// Count a rejection of a message that couldn't be decoded
+    ///
+    /// `agent_id` is `None` unless the sender is known; a message type that
+    /// isn't one of the protocol's is counted as [`UNKNOWN`].
+    pub fn rejected_raw(
+        &self,
+        room_id: &str,
+        agent_id: Option<&str>,
+        message_type: &str,
+        reason: &'static str,
+    ) {
+        let value = serde_json::Value::from(message_type);
+        let known_type = serde_json::from_value::<ResultMessageType>(value.clone()).is_ok()
+            || serde_json::from_value::<EnvelopeType>(value).is_ok();
+        let message_type = if known_type { message_type } else { UNKNOWN };
+        self.rejected_from(
+            Source::new(room_id, agent_id.unwrap_or(UNKNOWN), message_type),
+            reason,
+        );
+    }
+
+    fn rejected_from(&self, source: Source, reason: &'static str) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry.rejected.entry((source, reason)).or_default() += 1;
+    }
+
+    pub fn held(&self, room_id: &str, envelope: &Envelope) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .held
+            .entry(Source::of(room_id, envelope))
+            .or_default() += 1;
+    }
+
+    /// Record how long validating one candidate took
+    pub fn validated(&self, elapsed: Duration, valid: bool) {
+        let outcome = if valid { "valid" } else { "invalid" };
+        let mut registry = self.registry.lock().unwrap();
+        registry
+            .validation
+            .entry(outcome)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Replace the active grant counts with `(room_id, grants)` pairs
+    pub fn set_active_grants<'a>(&self, rooms: impl IntoIterator<Item = (&'a String, usize)>) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.active_grants = rooms
+            .into_iter()
+            .map(|(room_id, grants)| (room_id.clone(), grants))
+            .collect();
+    }
+
+    /// Everything in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "aor_gateway_messages_approved_total",
+            "counter",
+            "Messages republished to public",
+        );
+        for (source, count) in &registry.approved {
+            let _ = writeln!(
+                out,
+                "aor_gateway_messages_approved_total{{{}}} {}",
+                source.labels(),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "aor_gateway_messages_rejected_total",
+            "counter",
+            "Messages rejected on control, by reason",
+        );
+        for ((source, reason), count) in &registry.rejected {
+            let _ = writeln!(
+                out,
+                "aor_gateway_messages_rejected_total{{{},reason=\"{}\"}} {}",
+                source.labels(),
+                reason,
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "aor_gateway_messages_held_total",
+            "counter",
+            "Messages held for a moderator",
+        );
+        for (source, count) in &registry.held {
+            let _ = writeln!(
+                out,
+                "aor_gateway_messages_held_total{{{}}} {}",
+                source.labels(),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "aor_gateway_validation_duration_seconds",
+            "histogram",
+            "Time spent validating a candidate, including content policy",
+        );
+        for (outcome, histogram) in &registry.validation {
+            let name = "aor_gateway_validation_duration_seconds";
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{outcome=\"{}\",le=\"{}\"}} {}",
+                    name, outcome, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{outcome=\"{}\",le=\"+Inf\"}} {}",
+                name, outcome, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "{}_sum{{outcome=\"{}\"}} {}",
+                name, outcome, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{outcome=\"{}\"}} {}",
+                name, outcome, histogram.count
+            );
+        }
+
+        header(
+            &mut out,
+            "aor_gateway_active_grants",
+            "gauge",
+            "Mic grants currently active",
+        );
+        for (room_id, grants) in &registry.active_grants {
+            let _ = writeln!(
+                out,
+                "aor_gateway_active_grants{{room=\"{}\"}} {}",
+                escape(room_id),
+                grants
+            );
+        }
+        out
+    }
+}
+
+/// Serve `GET /metrics` until the process exits
+pub async fn serve(listener: TcpListener, metrics: Metrics) {
+    let app = Router::new()
+        .route("/metrics", get(scrape))
+        .with_state(metrics);
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("Metrics endpoint failed: {}", e);
+    }
+}
+
+async fn scrape(State(metrics): State<Metrics>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// A result's `message_type`, or the envelope type of anything else
+fn message_type(envelope: &Envelope) -> String {
+    match &envelope.payload {
+        Payload::Result(result) => result.message_type.to_string(),
+        _ => serde_json::to_value(envelope.message_type())
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_string))
+            .unwrap_or_default(),
+    }
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::message::{FindingContent, ResultContent};
+    use common::Sender;
+
+    fn say(agent_id: &str) -> Envelope {
+        Envelope::builder("ops", Sender::agent(agent_id)).say("hello")
+    }
+
+    /// Parse one sample line into its name, labels and value
+    fn parse_sample(line: &str) -> (&str, Vec<(String, String)>, f64) {
+        let (series, value) = line.rsplit_once(' ').expect("sample has a value");
+        let value = match value {
+            "+Inf" => f64::INFINITY,
+            value => value.parse().expect("sample value is a float"),
+        };
+        let Some((name, labels)) = series.split_once('{') else {
+            return (series, Vec::new(), value);
+        };
+        let mut rest = labels.strip_suffix('}').expect("labels are closed");
+        let mut parsed = Vec::new();
+        while !rest.is_empty() {
+            let (key, after) = rest.split_once("=\"").expect("label is key=\"value\"");
+            let mut label = String::new();
+            let mut chars = after.char_indices();
+            let end = loop {
+                match chars.next().expect("label value is closed") {
+                    (_, '\\') => match chars.next().expect("escape is complete").1 {
+                        'n' => label.push('\n'),
+                        c => label.push(c),
+                    },
+                    (i, '"') => break i,
+                    (_, c) => label.push(c),
+                }
+            };
+            parsed.push((key.to_string(), label));
+            rest = after[end + 1..]
+                .strip_prefix(',')
+                .unwrap_or(&after[end + 1..]);
+        }
+        (name, parsed, value)
+    }
+
+    #[test]
+    fn test_render_snapshot() {
+        let metrics = Metrics::default();
+        metrics.approved("ops", &say("math"));
+        metrics.approved("ops", &say("math"));
+        metrics.rejected(
+            "ops",
+            &Envelope::builder("ops", Sender::agent("math")).result(
+                "task_1",
+                ResultContent::Finding(FindingContent {
+                    bullets: None,
+                    text: Some("found".to_string()),
+                }),
+            ),
+            "no_mic_grant",
+            true,
+        );
+        metrics.rejected("ops", &say("spoofer-1"), "sender_spoofed", false);
+        metrics.rejected("ops", &say("spoofer-2"), "sender_spoofed", false);
+        metrics.rejected_raw("ops", None, "made_up", "unsupported_protocol_version");
+        metrics.rejected_raw(
+            "ops",
+            Some("math"),
+            "progress",
+            "unsupported_protocol_version",
+        );
+        metrics.held("ops", &say("math"));
+        metrics.validated(Duration::from_micros(300), true);
+        metrics.validated(Duration::from_millis(200), true);
+        let room = "a\"b".to_string();
+        metrics.set_active_grants([(&room, 2)]);
+
+        let rendered = metrics.render();
+        let mut expected = String::from(
+            "# HELP aor_gateway_messages_approved_total Messages republished to public
+# TYPE aor_gateway_messages_approved_total counter
+aor_gateway_messages_approved_total{room=\"ops\",agent=\"math\",message_type=\"say\"} 2
+# HELP aor_gateway_messages_rejected_total Messages rejected on control, by reason
+# TYPE aor_gateway_messages_rejected_total counter
+aor_gateway_messages_rejected_total{room=\"ops\",agent=\"math\",message_type=\"finding\",reason=\"no_mic_grant\"} 1
+aor_gateway_messages_rejected_total{room=\"ops\",agent=\"math\",message_type=\"progress\",reason=\"unsupported_protocol_version\"} 1
+aor_gateway_messages_rejected_total{room=\"ops\",agent=\"unknown\",message_type=\"say\",reason=\"sender_spoofed\"} 2
+aor_gateway_messages_rejected_total{room=\"ops\",agent=\"unknown\",message_type=\"unknown\",reason=\"unsupported_protocol_version\"} 1
+# HELP aor_gateway_messages_held_total Messages held for a moderator
+# TYPE aor_gateway_messages_held_total counter
+aor_gateway_messages_held_total{room=\"ops\",agent=\"math\",message_type=\"say\"} 1
+# HELP aor_gateway_validation_duration_seconds Time spent validating a candidate, including content policy
+# TYPE aor_gateway_validation_duration_seconds histogram
+",
+        );
+        for (bound, count) in LATENCY_BUCKETS.iter().zip([0, 0, 1, 1, 1, 1, 1, 1, 1, 1]) {
+            expected.push_str(&format!(
+                "aor_gateway_validation_duration_seconds_bucket{{outcome=\"valid\",le=\"{}\"}} {}\n",
+                bound, count
+            ));
+        }
+        expected.push_str(
+            "aor_gateway_validation_duration_seconds_bucket{outcome=\"valid\",le=\"+Inf\"} 2
+aor_gateway_validation_duration_seconds_sum{outcome=\"valid\"} 0.2003
+aor_gateway_validation_duration_seconds_count{outcome=\"valid\"} 2
+# HELP aor_gateway_active_grants Mic grants currently active
+# TYPE aor_gateway_active_grants gauge
+aor_gateway_active_grants{room=\"a\\\"b\"} 2
+",
+        );
+        assert_eq!(rendered, expected);
+
+        // Every sample belongs to the family declared above it, with well
+        // formed labels and a numeric value
+        let mut family: Option<(&str, &str)> = None;
+        let mut samples = 0;
+        for line in rendered.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                let mut words = comment.splitn(3, ' ');
+                match (words.next(), words.next(), words.next()) {
+                    (Some("TYPE"), Some(name), Some(kind)) => {
+                        assert!(["counter", "gauge", "histogram"].contains(&kind));
+                        family = Some((name, kind));
+                    }
+                    (Some("HELP"), Some(_), Some(_)) => {}
+                    _ => panic!("unexpected comment: {}", line),
+                }
+                continue;
+            }
+            let (name, labels, value) = parse_sample(line);
+            let (family, kind) = family.expect("sample follows a TYPE line");
+            let suffix = name.strip_prefix(family).expect("sample is in its family");
+            let keys: Vec<&str> = labels.iter().map(|(key, _)| key.as_str()).collect();
+            match (kind, suffix) {
+                ("counter", "") => {
+                    assert_eq!(keys[..3], ["room", "agent", "message_type"]);
+                    assert!(keys.len() == 3 || keys[3..] == ["reason"]);
+                }
+                ("gauge", "") => assert_eq!(keys, ["room"]),
+                ("histogram", "_bucket") => assert_eq!(keys, ["outcome", "le"]),
+                ("histogram", "_sum" | "_count") => assert_eq!(keys, ["outcome"]),
+                _ => panic!("unexpected sample: {}", line),
+            }
+            assert!(value >= 0.0);
+            samples += 1;
+        }
+        assert_eq!(samples, 20);
+
+        let (_, labels, _) = parse_sample(rendered.lines().last().unwrap());
+        assert_eq!(labels, [("room".to_string(), "a\"b".to_string())]);
+    }
+}
@@ -1,112 +1,519 @@
 //! Mic grant tracking and validation
 
-use common::MicGrantPayload;
-use std::collections::HashMap;
+use common::{MicExpiryReason, MicGrantPayload, MicGrantState, ResultMessageType, SignatureError};
+use std::collections::{BTreeMap, HashMap};
+
+/// How long a revoke of a grant this gateway never saw blocks it from being
+/// adopted, since the grant's own expiry is unknown
+const UNKNOWN_REVOKE_RETENTION_SECS: u64 = 3600;
 
 /// Tracks active mic grants per agent
 #[derive(Debug, Default)]
 pub struct MicGrantTracker {
     /// Key: (agent_id, task_id)
-    grants: HashMap<(String, String), MicGrant>,
-}
-
-#[derive(Debug, Clone)]
-pub struct MicGrant {
-    #[allow(dead_code)]
-    pub task_id: String,
-    #[allow(dead_code)]
-    pub agent_id: String,
-    pub max_messages: u32,
-    pub messages_sent: u32,
-    pub allowed_message_types: Vec<String>,
-    pub expires_at: u64,
+    grants: HashMap<(String, String), MicGrantState>,
+    /// Revoked and swept grants, kept until they would have expired so that a
+    /// snapshot published before they ended can't bring them back
+    ended: HashMap<(String, String), u64>,
 }
 
 impl MicGrantTracker {
-    pub fn new() -> Self {
-        Self::default()
+    /// Rebuild a tracker from previously saved grants
+    pub fn from_grants(grants: impl IntoIterator<Item = MicGrantState>) -> Self {
+        let mut tracker = Self::default();
+        for state in grants {
+            tracker.grants.insert(key(&state.grant), state);
+        }
+        tracker
+    }
+
+    /// Active grants, for persistence and snapshots
+    pub fn grants(&self) -> impl Iterator<Item = &MicGrantState> {
+        self.grants.values()
+    }
+
+    /// Grant for `(agent_id, task_id)`, if active
+    pub fn get(&self, agent_id: &str, task_id: &str) -> Option<&MicGrantState> {
+        self.grants
+            .get(&(agent_id.to_string(), task_id.to_string()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.grants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grants.is_empty()
     }
 
     /// Record a new mic grant
+    ///
+    /// A grant reissued after ending is live again.
     pub fn grant(&mut self, payload: MicGrantPayload) {
-        let grant = MicGrant {
-            task_id: payload.task_id.clone(),
-            agent_id: payload.agent_id.clone(),
-            max_messages: payload.max_messages,
-            messages_sent: 0,
-            allowed_message_types: payload
-                .allowed_message_types
-                .iter()
-                .map(|t| format!("{:?}", t).to_lowercase())
-                .collect(),
-            expires_at: payload.expires_at,
-        };
+        self.ended.remove(&key(&payload));
+        self.grants.insert(
+            key(&payload),
+            MicGrantState {
+                grant: payload,
+                messages_sent: 0,
+                sent_by: BTreeMap::new(),
+            },
+        );
+    }
 
-        self.grants
-            .insert((payload.agent_id, payload.task_id), grant);
+    /// Adopt grants from another gateway's snapshot
+    ///
+    /// Unknown unexpired grants are added unless they were revoked or swept
+    /// here. For known ones each member's higher count wins and
+    /// `messages_sent` becomes their sum, so messages two members approved
+    /// under the same grant both count, and merging the same snapshot twice
+    /// is harmless. Returns whether anything changed.
+    pub fn merge(
+        &mut self,
+        grants: impl IntoIterator<Item = MicGrantState>,
+        current_ts: u64,
+    ) -> bool {
+        let mut changed = false;
+        for state in grants {
+            let key = key(&state.grant);
+            if current_ts > state.grant.expires_at || self.ended.contains_key(&key) {
+                continue;
+            }
+            match self.grants.get_mut(&key) {
+                Some(known) => {
+                    for (member, sent) in state.sent_by {
+                        let count = known.sent_by.entry(member).or_default();
+                        if sent > *count {
+                            *count = sent;
+                            changed = true;
+                        }
+                    }
+                    // Totals without per-member counts predate them, so the
+                    // higher one still wins
+                    let total = known
+                        .sent_by
+                        .values()
+                        .sum::<u32>()
+                        .max(known.messages_sent)
+                        .max(state.messages_sent);
+                    if total != known.messages_sent {
+                        known.messages_sent = total;
+                        changed = true;
+                    }
+                }
+                None => {
+                    self.grants.insert(key, state);
+                    changed = true;
+                }
+            }
+        }
+        changed
     }
 
     /// Validate a message against an active mic grant
+    ///
+    /// The message isn't counted until [`count_message`](Self::count_message),
+    /// so checks after this one can still reject it for free.
     pub fn validate(
-        &mut self,
+        &self,
         agent_id: &str,
         task_id: &str,
-        message_type: &str,
+        message_type: ResultMessageType,
         current_ts: u64,
     ) -> Result<(), ValidationError> {
-        let key = (agent_id.to_string(), task_id.to_string());
-
-        let grant = self
-            .grants
-            .get_mut(&key)
+        let state = self
+            .get(agent_id, task_id)
             .ok_or(ValidationError::NoMicGrant)?;
+        let grant = &state.grant;
 
         // Check expiration
         if current_ts > grant.expires_at {
-            return Err(ValidationError::MicGrantExpired);
+            return Err(ValidationError::MicGrantExpired {
+                expires_at: grant.expires_at,
+                current_ts,
+            });
         }
 
         // Check message type allowed
-        if !grant
-            .allowed_message_types
-            .contains(&message_type.to_string())
-        {
-            return Err(ValidationError::MessageTypeNotAllowed);
+        if !grant.allowed_message_types.contains(&message_type) {
+            return Err(ValidationError::MessageTypeNotAllowed {
+                message_type: message_type.to_string(),
+                allowed: grant
+                    .allowed_message_types
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+            });
         }
 
         // Check message count
-        if grant.messages_sent >= grant.max_messages {
-            return Err(ValidationError::MessageLimitExceeded);
+        if state.messages_sent >= grant.max_messages {
+            return Err(ValidationError::MessageLimitExceeded {
+                sent: state.messages_sent,
+                max: grant.max_messages,
+            });
         }
 
-        // Increment counter
-        grant.messages_sent += 1;
-
         Ok(())
     }
 
+    /// Count a message approved by gateway `member` against its grant
+    pub fn count_message(&mut self, agent_id: &str, task_id: &str, member: &str) {
+        if let Some(state) = self
+            .grants
+            .get_mut(&(agent_id.to_string(), task_id.to_string()))
+        {
+            state.messages_sent += 1;
+            *state.sent_by.entry(member.to_string()).or_default() += 1;
+        }
+    }
+
+    /// Remove grants that can no longer be used, returning why each ended
+    pub fn sweep(&mut self, current_ts: u64) -> Vec<(MicGrantState, MicExpiryReason)> {
+        self.ended.retain(|_, until| current_ts <= *until);
+        let mut ended = Vec::new();
+        self.grants.retain(|key, state| {
+            let reason = if current_ts > state.grant.expires_at {
+                MicExpiryReason::Expired
+            } else if state.messages_sent >= state.grant.max_messages {
+                MicExpiryReason::Exhausted
+            } else {
+                return true;
+            };
+            if reason == MicExpiryReason::Exhausted {
+                self.ended.insert(key.clone(), state.grant.expires_at);
+            }
+            ended.push((state.clone(), reason));
+            false
+        });
+        ended
+    }
+
     /// Revoke a mic grant (cleanup)
-    pub fn revoke(&mut self, agent_id: &str, task_id: &str) {
-        self.grants
-            .remove(&(agent_id.to_string(), task_id.to_string()));
+    ///
+    /// The revoke may overtake the snapshot that would have told this gateway
+    /// about the grant, so unknown grants are remembered as revoked too.
+    pub fn revoke(&mut self, agent_id: &str, task_id: &str, current_ts: u64) {
+        let key = (agent_id.to_string(), task_id.to_string());
+        let until = match self.grants.remove(&key) {
+            Some(state) => state.grant.expires_at,
+            None => current_ts.saturating_add(UNKNOWN_REVOKE_RETENTION_SECS),
+        };
+        self.ended.insert(key, until);
     }
 }
 
+fn key(grant: &MicGrantPayload) -> (String, String) {
+    (grant.agent_id.clone(), grant.task_id.clone())
+}
+
+/// Why a candidate was rejected
+///
+/// [`reason`](Self::reason) is the stable code sent in every `reject`;
+/// [`detail`](Self::detail) explains it and is only sent with verbose
+/// rejections.
 #[derive(Debug)]
 pub enum ValidationError {
     NoMicGrant,
-    MicGrantExpired,
-    MessageTypeNotAllowed,
-    MessageLimitExceeded,
+    MicGrantExpired {
+        expires_at: u64,
+        current_ts: u64,
+    },
+    MessageTypeNotAllowed {
+        message_type: String,
+        allowed: Vec<String>,
+    },
+    MessageLimitExceeded {
+        sent: u32,
+        max: u32,
+    },
+    UnsupportedProtocolVersion(String),
+    RoomMismatch {
+        envelope_room: String,
+    },
+    /// The agent's or room's bucket that ran out
+    RateLimited {
+        scope: &'static str,
+        limit: &'static str,
+    },
+    ContentTypeMismatch,
+    EmptyContent {
+        field: String,
+    },
+    ContentTooLarge {
+        field: String,
+        len: usize,
+        max: usize,
+    },
+    InvalidUrl,
+    UrlSchemeNotAllowed {
+        scheme: String,
+    },
+    ModeratorDenied {
+        reason: Option<String>,
+    },
+    ModerationTimeout {
+        timeout_secs: u64,
+    },
+    /// Rejected by the named content policy rule
+    PolicyViolation(String),
+    ValidationTimeout {
+        budget_ms: u64,
+    },
+    /// `from.id` doesn't match the publishing client's broker-supplied identity
+    SenderSpoofed {
+        claimed: String,
+        client: Option<String>,
+    },
+    InvalidSignature(SignatureError),
+    /// A mic grant or revoke from a sender not allowed to issue them
+    UnauthorizedIssuer {
+        issuer: String,
+    },
+}
+
+impl ValidationError {
+    /// Terse reason code
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ValidationError::NoMicGrant => "no_mic_grant",
+            ValidationError::MicGrantExpired { .. } => "mic_grant_expired",
+            ValidationError::MessageTypeNotAllowed { .. } => "message_type_not_allowed",
+            ValidationError::MessageLimitExceeded { .. } => "message_limit_exceeded",
+            ValidationError::UnsupportedProtocolVersion(_) => "unsupported_protocol_version",
+            ValidationError::RoomMismatch { .. } => "room_mismatch",
+            ValidationError::RateLimited { .. } => "rate_limited",
+            ValidationError::ContentTypeMismatch => "content_type_mismatch",
+            ValidationError::EmptyContent { .. } => "empty_content",
+            ValidationError::ContentTooLarge { .. } => "content_too_large",
+            ValidationError::InvalidUrl => "invalid_url",
+            ValidationError::UrlSchemeNotAllowed { .. } => "url_scheme_not_allowed",
+            ValidationError::ModeratorDenied { .. } => "moderator_denied",
+            ValidationError::ModerationTimeout { .. } => "moderation_timeout",
+            ValidationError::PolicyViolation(_) => "policy_violation",
+            ValidationError::ValidationTimeout { .. } => "validation_timeout",
+            ValidationError::SenderSpoofed { .. } => "sender_spoofed",
+            ValidationError::InvalidSignature(_) => "invalid_signature",
+            ValidationError::UnauthorizedIssuer { .. } => "unauthorized_issuer",
+        }
+    }
+
+    /// Which constraint failed and the counters involved, when there's more to say
+    pub fn detail(&self) -> Option<String> {
+        let detail = match self {
+            ValidationError::NoMicGrant
+            | ValidationError::ContentTypeMismatch
+            | ValidationError::InvalidUrl => return None,
+            ValidationError::MicGrantExpired {
+                expires_at,
+                current_ts,
+            } => format!(
+                "grant expired at {}, {}s ago",
+                expires_at,
+                current_ts.saturating_sub(*expires_at)
+            ),
+            ValidationError::MessageTypeNotAllowed {
+                message_type,
+                allowed,
+            } => format!("{} not in [{}]", message_type, allowed.join(", ")),
+            ValidationError::MessageLimitExceeded { sent, max } => {
+                format!("{} of {} messages already sent", sent, max)
+            }
+            ValidationError::UnsupportedProtocolVersion(version) => {
+                format!("version {} not supported", version)
+            }
+            ValidationError::RoomMismatch { envelope_room } => {
+                format!("envelope addressed to room {}", envelope_room)
+            }
+            ValidationError::RateLimited { scope, limit } => {
+                format!("{} {} limit reached", scope, limit)
+            }
+            ValidationError::EmptyContent { field } => format!("{} is empty", field),
+            ValidationError::ContentTooLarge { field, len, max } => {
+                format!("{} is {} long, max {}", field, len, max)
+            }
+            ValidationError::UrlSchemeNotAllowed { scheme } => {
+                format!("scheme {} not allowed", scheme)
+            }
+            ValidationError::ModeratorDenied { reason } => return reason.clone(),
+            ValidationError::ModerationTimeout { timeout_secs } => {
+                format!("no moderator decision within {}s", timeout_secs)
+            }
+            ValidationError::PolicyViolation(rule) => format!("rule {}", rule),
+            ValidationError::ValidationTimeout { budget_ms } => {
+                format!("validation took over {}ms", budget_ms)
+            }
+            ValidationError::SenderSpoofed { claimed, client } => match client {
+                Some(client) => format!("sent as {} by client {}", claimed, client),
+                None => format!("sent as {} without a client identity", claimed),
+            },
+            ValidationError::InvalidSignature(e) => e.to_string(),
+            ValidationError::UnauthorizedIssuer { issuer } => {
+                format!("{} may not issue mic grants", issuer)
+            }
+        };
+        Some(detail)
+    }
 }
 
 impl std::fmt::Display for ValidationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ValidationError::NoMicGrant => write!(f, "no_mic_grant"),
-            ValidationError::MicGrantExpired => write!(f, "mic_grant_expired"),
-            ValidationError::MessageTypeNotAllowed => write!(f, "message_type_not_allowed"),
-            ValidationError::MessageLimitExceeded => write!(f, "message_limit_exceeded"),
+        match self.detail() {
+            Some(detail) => write!(f, "{} ({})", self.reason(), detail),
+            None => f.write_str(self.reason()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(agent_id: &str, max_messages: u32, expires_at: u64) -> MicGrantPayload {
+        MicGrantPayload {
+            task_id: "task_1".to_string(),
+            agent_id: agent_id.to_string(),
+            max_messages,
+            allowed_message_types: vec![ResultMessageType::Finding, ResultMessageType::Result],
+            expires_at,
+        }
+    }
+
+    fn state(agent_id: &str, expires_at: u64, messages_sent: u32) -> MicGrantState {
+        MicGrantState {
+            grant: payload(agent_id, 5, expires_at),
+            messages_sent,
+            sent_by: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate_counts_messages() {
+        let mut tracker = MicGrantTracker::default();
+        assert!(matches!(
+            tracker.validate("math", "task_1", ResultMessageType::Result, 100),
+            Err(ValidationError::NoMicGrant)
+        ));
+
+        tracker.grant(payload("math", 2, 200));
+        assert!(matches!(
+            tracker.validate("math", "task_1", ResultMessageType::Ack, 100),
+            Err(ValidationError::MessageTypeNotAllowed { .. })
+        ));
+        for message_type in [ResultMessageType::Finding, ResultMessageType::Result] {
+            // Only counted messages use up the grant
+            for _ in 0..2 {
+                tracker
+                    .validate("math", "task_1", message_type.clone(), 100)
+                    .unwrap();
+            }
+            tracker.count_message("math", "task_1", "gateway");
         }
+        assert!(matches!(
+            tracker.validate("math", "task_1", ResultMessageType::Result, 100),
+            Err(ValidationError::MessageLimitExceeded { sent: 2, max: 2 })
+        ));
+        assert!(matches!(
+            tracker.validate("math", "task_1", ResultMessageType::Result, 201),
+            Err(ValidationError::MicGrantExpired { .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_keeps_higher_count() {
+        let mut tracker = MicGrantTracker::from_grants([state("math", 200, 3)]);
+
+        // A lower count for a known grant loses
+        assert!(!tracker.merge([state("math", 200, 1)], 100));
+        assert_eq!(tracker.get("math", "task_1").unwrap().messages_sent, 3);
+
+        // A higher count wins, and merging it again changes nothing
+        assert!(tracker.merge([state("math", 200, 4)], 100));
+        assert!(!tracker.merge([state("math", 200, 4)], 100));
+        assert_eq!(tracker.get("math", "task_1").unwrap().messages_sent, 4);
+
+        // Unknown grants are adopted unless already expired
+        assert!(tracker.merge([state("cmd", 200, 0), state("old", 99, 0)], 100));
+        assert!(tracker.get("cmd", "task_1").is_some());
+        assert!(tracker.get("old", "task_1").is_none());
+        assert_eq!(tracker.len(), 2);
+    }
+
+    #[test]
+    fn test_merge_sums_member_counts() {
+        let mut a = MicGrantTracker::default();
+        a.grant(payload("math", 5, 200));
+        a.count_message("math", "task_1", "gateway-a");
+        let mut b = MicGrantTracker::from_grants(a.grants().cloned());
+
+        // Both members approve from the same base, e.g. while a heartbeat is late
+        a.count_message("math", "task_1", "gateway-a");
+        b.count_message("math", "task_1", "gateway-b");
+        b.count_message("math", "task_1", "gateway-b");
+
+        assert!(a.merge(b.grants().cloned(), 100));
+        assert!(b.merge(a.grants().cloned(), 100));
+        for tracker in [&a, &b] {
+            let state = tracker.get("math", "task_1").unwrap();
+            assert_eq!(state.messages_sent, 4);
+            assert_eq!(state.sent_by["gateway-a"], 2);
+            assert_eq!(state.sent_by["gateway-b"], 2);
+        }
+        assert!(!a.merge(b.grants().cloned(), 100));
+    }
+
+    #[test]
+    fn test_merge_ignores_ended_grants() {
+        let mut tracker = MicGrantTracker::default();
+        tracker.grant(payload("math", 5, 200));
+        tracker.grant(payload("cmd", 1, 200));
+        let snapshot: Vec<MicGrantState> = tracker.grants().cloned().collect();
+
+        // Revoked and exhausted after the snapshot was published
+        tracker.revoke("math", "task_1", 100);
+        tracker.count_message("cmd", "task_1", "gateway");
+        tracker.sweep(100);
+        assert!(!tracker.merge(snapshot.clone(), 100));
+        assert!(tracker.is_empty());
+
+        // A revoke that arrives before the grant is ever seen
+        tracker.revoke("late", "task_1", 100);
+        assert!(!tracker.merge([state("late", 200, 0)], 101));
+        assert!(tracker.get("late", "task_1").is_none());
+
+        // Reissued grants are live again, and tombstones end with the grant
+        tracker.grant(payload("math", 5, 300));
+        assert!(tracker.get("math", "task_1").is_some());
+        tracker.sweep(201);
+        assert_eq!(tracker.ended.len(), 1);
+        assert!(tracker
+            .ended
+            .contains_key(&("late".to_string(), "task_1".to_string())));
+    }
+
+    #[test]
+    fn test_sweep_removes_ended_grants() {
+        let mut tracker = MicGrantTracker::from_grants([
+            state("expired", 99, 0),
+            state("exhausted", 200, 5),
+            state("active", 200, 4),
+        ]);
+
+        let mut ended: Vec<(String, MicExpiryReason)> = tracker
+            .sweep(100)
+            .into_iter()
+            .map(|(state, reason)| (state.grant.agent_id, reason))
+            .collect();
+        ended.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            ended,
+            vec![
+                ("exhausted".to_string(), MicExpiryReason::Exhausted),
+                ("expired".to_string(), MicExpiryReason::Expired),
+            ]
+        );
+        assert_eq!(tracker.len(), 1);
+        assert!(tracker.get("active", "task_1").is_some());
+        assert!(tracker.sweep(100).is_empty());
     }
 }
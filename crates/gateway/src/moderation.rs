@@ -0,0 +1,193 @@
+//! Human-in-the-loop moderation
+//!
+//! Candidates matching a hold rule pass every other check first (and count
+//! against their mic grant), then wait here until a moderator approves or
+//! denies them on `rooms/{roomId}/moderation`, or until they time out.
+
+use common::message::{Payload, ResultContent};
+use common::{Envelope, Redaction};
+use std::collections::HashMap;
+
+/// Which valid candidates need a moderator before publication
+#[derive(Debug, Default)]
+pub struct HoldRules {
+    /// Result message types, e.g. `result`
+    pub message_types: Vec<String>,
+    /// Risk severities, compared case-insensitively
+    pub risk_severities: Vec<String>,
+    /// Agents whose every message is held
+    pub agents: Vec<String>,
+}
+
+impl HoldRules {
+    pub fn is_empty(&self) -> bool {
+        self.message_types.is_empty() && self.risk_severities.is_empty() && self.agents.is_empty()
+    }
+
+    /// Rule that holds `envelope`, if any, as `kind:value`
+    pub fn matches(&self, envelope: &Envelope) -> Option<String> {
+        if self.agents.contains(&envelope.from.id) {
+            return Some(format!("agent:{}", envelope.from.id));
+        }
+        let Payload::Result(result) = &envelope.payload else {
+            return None;
+        };
+        let message_type = result.message_type.to_string();
+        if self.message_types.contains(&message_type) {
+            return Some(format!("message_type:{}", message_type));
+        }
+        if let ResultContent::Risk(risk) = &result.content {
+            let severity = risk.severity.as_deref().unwrap_or_default();
+            if self
+                .risk_severities
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(severity))
+            {
+                return Some(format!("risk_severity:{}", severity.to_ascii_lowercase()));
+            }
+        }
+        None
+    }
+}
+
+/// A candidate waiting for a moderator
+#[derive(Debug)]
+pub struct Held {
+    pub room_id: String,
+    pub envelope: Envelope,
+    /// Policy redactions already applied to `envelope`
+    pub redactions: Vec<Redaction>,
+    pub expires_at: u64,
+}
+
+/// Held candidates keyed by message ID
+#[derive(Debug, Default)]
+pub struct ModerationQueue {
+    pending: HashMap<String, Held>,
+}
+
+impl ModerationQueue {
+    pub fn hold(&mut self, held: Held) {
+        self.pending.insert(held.envelope.id.clone(), held);
+    }
+
+    /// Remove a held candidate from `room_id` so it can be decided
+    pub fn take(&mut self, room_id: &str, message_id: &str) -> Option<Held> {
+        if self.pending.get(message_id)?.room_id != room_id {
+            return None;
+        }
+        self.pending.remove(message_id)
+    }
+
+    /// Remove and return candidates no moderator decided on in time
+    pub fn expire(&mut self, current_ts: u64) -> Vec<Held> {
+        let expired: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, held)| current_ts > held.expires_at)
+            .map(|(id, _)| id.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|id| self.pending.remove(id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::message::{AckContent, ResultOutcome, RiskContent};
+    use common::Sender;
+
+    fn envelope(agent_id: &str, content: ResultContent) -> Envelope {
+        Envelope::builder("ops", Sender::agent(agent_id)).result("task_1", content)
+    }
+
+    fn risk(severity: &str) -> ResultContent {
+        ResultContent::Risk(RiskContent {
+            text: "Deleting the bucket is irreversible".to_string(),
+            severity: Some(severity.to_string()),
+            mitigation: None,
+        })
+    }
+
+    #[test]
+    fn test_hold_rules() {
+        let rules = HoldRules {
+            message_types: vec!["result".to_string()],
+            risk_severities: vec!["high".to_string()],
+            agents: vec!["intern".to_string()],
+        };
+        let ack = ResultContent::Ack(AckContent {
+            text: "on it".to_string(),
+        });
+        let result = ResultContent::Result(ResultOutcome {
+            text: "done".to_string(),
+        });
+
+        assert_eq!(rules.matches(&envelope("math", ack.clone())), None);
+        assert_eq!(
+            rules.matches(&envelope("intern", ack)).as_deref(),
+            Some("agent:intern")
+        );
+        assert_eq!(
+            rules.matches(&envelope("math", result)).as_deref(),
+            Some("message_type:result")
+        );
+        assert_eq!(
+            rules.matches(&envelope("math", risk("HIGH"))).as_deref(),
+            Some("risk_severity:high")
+        );
+        assert_eq!(rules.matches(&envelope("math", risk("low"))), None);
+        assert!(HoldRules::default().is_empty());
+    }
+
+    fn held(room_id: &str, expires_at: u64) -> Held {
+        Held {
+            room_id: room_id.to_string(),
+            envelope: envelope("math", risk("high")),
+            redactions: Vec::new(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn test_take_decides_once() {
+        let mut queue = ModerationQueue::default();
+        let held = held("ops", 100);
+        let id = held.envelope.id.clone();
+        queue.hold(held);
+
+        // Unknown IDs and other rooms' decisions leave it held
+        assert!(queue.take("ops", "msg_unknown").is_none());
+        assert!(queue.take("other", &id).is_none());
+
+        let taken = queue.take("ops", &id).unwrap();
+        assert_eq!(taken.envelope.id, id);
+        assert!(queue.take("ops", &id).is_none());
+    }
+
+    #[test]
+    fn test_expire_after_timeout() {
+        let mut queue = ModerationQueue::default();
+        let soon = held("ops", 100);
+        let later = held("ops", 200);
+        let (soon_id, later_id) = (soon.envelope.id.clone(), later.envelope.id.clone());
+        queue.hold(soon);
+        queue.hold(later);
+
+        // Still decidable up to and including `expires_at`
+        assert!(queue.expire(100).is_empty());
+        let expired: Vec<String> = queue
+            .expire(101)
+            .into_iter()
+            .map(|held| held.envelope.id)
+            .collect();
+        assert_eq!(expired, vec![soon_id.clone()]);
+
+        assert!(queue.take("ops", &soon_id).is_none());
+        assert!(queue.take("ops", &later_id).is_some());
+        assert!(queue.expire(u64::MAX).is_empty());
+    }
+}
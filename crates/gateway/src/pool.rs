@@ -0,0 +1,135 @@
+//! Gateway pool membership and grant ownership
+//!
+//! Pooled gateways share `public_candidates` through an MQTT shared
+//! subscription, so the broker hands each candidate to an arbitrary member.
+//! To count every grant's messages in exactly one place, each grant is owned
+//! by one member, chosen by rendezvous hashing over the live members. A member
+//! receiving a candidate it doesn't own forwards it to the owner's
+//! `rooms/{roomId}/gateways/{gatewayId}/candidates` topic.
+//!
+//! Members are discovered from the heartbeats every gateway already sends;
+//! their IDs start with [`GATEWAY_ID_PREFIX`]. A member whose heartbeat is
+//! late keeps its grants until it times out, but candidates aren't forwarded
+//! to it in the meantime, so they aren't lost if it has died.
+
+use std::collections::HashMap;
+
+/// Sender ID prefix that marks a heartbeat as coming from a gateway
+pub const GATEWAY_ID_PREFIX: &str = "gateway";
+
+/// A member is considered gone after missing this many seconds of heartbeats
+const MEMBER_TIMEOUT_SECS: u64 = 30;
+
+/// Candidates are only forwarded to members heard from this recently, a bit
+/// over one 10s heartbeat interval
+const FORWARD_TIMEOUT_SECS: u64 = 15;
+
+#[derive(Debug)]
+pub struct GatewayPool {
+    self_id: String,
+    /// Last heartbeat time of every other member
+    members: HashMap<String, u64>,
+}
+
+impl GatewayPool {
+    pub fn new(self_id: impl Into<String>) -> Self {
+        Self {
+            self_id: self_id.into(),
+            members: HashMap::new(),
+        }
+    }
+
+    pub fn self_id(&self) -> &str {
+        &self.self_id
+    }
+
+    /// Record a heartbeat from another gateway
+    pub fn observe(&mut self, gateway_id: &str, current_ts: u64) {
+        if gateway_id != self.self_id {
+            self.members.insert(gateway_id.to_string(), current_ts);
+        }
+    }
+
+    /// Whether `member` sent a heartbeat recently enough to forward to it
+    pub fn is_fresh(&self, member: &str, current_ts: u64) -> bool {
+        member == self.self_id
+            || self
+                .members
+                .get(member)
+                .is_some_and(|seen| current_ts.saturating_sub(*seen) <= FORWARD_TIMEOUT_SECS)
+    }
+
+    /// Member that counts messages for `(room_id, agent_id, task_id)`
+    pub fn owner(&self, room_id: &str, agent_id: &str, task_id: &str, current_ts: u64) -> &str {
+        let live = self
+            .members
+            .iter()
+            .filter(|(_, seen)| current_ts.saturating_sub(**seen) <= MEMBER_TIMEOUT_SECS)
+            .map(|(id, _)| id.as_str());
+
+        std::iter::once(self.self_id.as_str())
+            .chain(live)
+            .max_by_key(|member| (weight(member, room_id, agent_id, task_id), *member))
+            .unwrap_or(&self.self_id)
+    }
+}
+
+/// Rendezvous weight of a member for a grant
+///
+/// FNV-1a plus a splitmix64 finalizer rather than `DefaultHasher`, whose
+/// output isn't guaranteed to agree between builds, so every member ranks
+/// owners identically.
+fn weight(member: &str, room_id: &str, agent_id: &str, task_id: &str) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut hash = OFFSET;
+    for part in [member, room_id, agent_id, task_id] {
+        // Separator keeps ("ab", "c") and ("a", "bc") apart
+        for byte in part.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+
+    hash ^= hash >> 30;
+    hash = hash.wrapping_mul(0xbf58476d1ce4e5b9);
+    hash ^= hash >> 27;
+    hash = hash.wrapping_mul(0x94d049bb133111eb);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owner_spreads_and_times_out() {
+        let mut pool = GatewayPool::new("gateway-a");
+        pool.observe("gateway-b", 100);
+        pool.observe("gateway-a", 100);
+
+        let owners: Vec<&str> = (0..20)
+            .map(|i| pool.owner("ops", "math", &format!("task_{}", i), 100))
+            .collect();
+        assert!(owners.contains(&"gateway-a"));
+        assert!(owners.contains(&"gateway-b"));
+
+        // Not forwarded to once late, but still owning until it times out
+        let task = (0..20)
+            .map(|i| format!("task_{}", i))
+            .find(|task| pool.owner("ops", "math", task, 100) == "gateway-b")
+            .unwrap();
+        assert!(pool.is_fresh("gateway-b", 100 + FORWARD_TIMEOUT_SECS));
+        assert!(!pool.is_fresh("gateway-b", 101 + FORWARD_TIMEOUT_SECS));
+        assert_eq!(
+            pool.owner("ops", "math", &task, 100 + MEMBER_TIMEOUT_SECS),
+            "gateway-b"
+        );
+        assert_eq!(
+            pool.owner("ops", "math", &task, 101 + MEMBER_TIMEOUT_SECS),
+            "gateway-a"
+        );
+        assert!(pool.is_fresh("gateway-a", u64::MAX));
+    }
+}
@@ -0,0 +1,270 @@
+//! Token-bucket rate limiting per agent and per room
+
+use crate::mic_grant::ValidationError;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Configured limits; a rate of 0 disables that limit
+///
+/// A burst is how many messages may arrive at once before the rate applies.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    pub agent_messages_per_sec: f64,
+    pub agent_message_burst: f64,
+    pub agent_bytes_per_min: f64,
+    pub room_messages_per_sec: f64,
+    pub room_message_burst: f64,
+    pub room_bytes_per_min: f64,
+}
+
+/// Message and byte buckets for one agent or one room
+#[derive(Debug)]
+struct Buckets {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+}
+
+impl Buckets {
+    fn new(messages_per_sec: f64, message_burst: f64, bytes_per_min: f64, now: Instant) -> Self {
+        Self {
+            // A burst of messages and one minute of bytes may arrive at once
+            messages: TokenBucket::new(message_burst.max(1.0), messages_per_sec, now),
+            bytes: TokenBucket::new(bytes_per_min, bytes_per_min / 60.0, now),
+        }
+    }
+
+    /// Whether both buckets have refilled, making them as good as new
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.messages.is_full(now) && self.bytes.is_full(now)
+    }
+
+    /// Name of the bucket that can't take the message, if any
+    fn exhausted(&mut self, bytes: f64, now: Instant) -> Option<&'static str> {
+        if !self.messages.has(1.0, now) {
+            Some("messages per second")
+        } else if !self.bytes.has(bytes, now) {
+            Some("bytes per minute")
+        } else {
+            None
+        }
+    }
+
+    fn take(&mut self, bytes: f64) {
+        self.messages.take(1.0);
+        self.bytes.take(bytes);
+    }
+}
+
+/// Rate limiter for one room
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    room: Option<Buckets>,
+    agents: HashMap<String, Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            room: None,
+            agents: HashMap::new(),
+        }
+    }
+
+    /// Account for a `bytes`-sized message from `agent_id`
+    ///
+    /// Tokens are only taken when both the agent and the room have room for
+    /// the message, so a rejected message doesn't count against either.
+    pub fn check(
+        &mut self,
+        agent_id: &str,
+        bytes: usize,
+        now: Instant,
+    ) -> Result<(), ValidationError> {
+        let limits = self.limits;
+        let bytes = bytes as f64;
+
+        let room = self.room.get_or_insert_with(|| {
+            Buckets::new(
+                limits.room_messages_per_sec,
+                limits.room_message_burst,
+                limits.room_bytes_per_min,
+                now,
+            )
+        });
+        let agent = self.agents.entry(agent_id.to_string()).or_insert_with(|| {
+            Buckets::new(
+                limits.agent_messages_per_sec,
+                limits.agent_message_burst,
+                limits.agent_bytes_per_min,
+                now,
+            )
+        });
+
+        if let Some(limit) = agent.exhausted(bytes, now) {
+            return Err(ValidationError::RateLimited {
+                scope: "agent",
+                limit,
+            });
+        }
+        if let Some(limit) = room.exhausted(bytes, now) {
+            return Err(ValidationError::RateLimited {
+                scope: "room",
+                limit,
+            });
+        }
+        agent.take(bytes);
+        room.take(bytes);
+        Ok(())
+    }
+
+    /// Forget agents whose buckets have refilled
+    ///
+    /// A full bucket limits exactly like a new one, so this changes no limits
+    /// but keeps senders that come and go (or spoofed IDs) from piling up.
+    /// Returns whether the room's buckets are full too, so the limiter can go.
+    pub fn evict_idle(&mut self, now: Instant) -> bool {
+        self.agents.retain(|_, agent| !agent.is_full(now));
+        self.agents.is_empty() && self.room.as_mut().is_none_or(|room| room.is_full(now))
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, refill_per_sec: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec,
+            last_refill: now,
+        }
+    }
+
+    fn disabled(&self) -> bool {
+        self.refill_per_sec <= 0.0
+    }
+
+    /// Refill for the time elapsed, then report whether `amount` is available
+    fn has(&mut self, amount: f64, now: Instant) -> bool {
+        if self.disabled() {
+            return true;
+        }
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+        self.tokens >= amount
+    }
+
+    fn take(&mut self, amount: f64) {
+        if !self.disabled() {
+            self.tokens -= amount;
+        }
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.has(self.capacity, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimits {
+            agent_messages_per_sec: 2.0,
+            agent_message_burst: 4.0,
+            agent_bytes_per_min: 600.0,
+            room_messages_per_sec: 4.0,
+            room_message_burst: 6.0,
+            room_bytes_per_min: 0.0,
+        })
+    }
+
+    fn limit(result: Result<(), ValidationError>) -> Option<(&'static str, &'static str)> {
+        match result {
+            Ok(()) => None,
+            Err(ValidationError::RateLimited { scope, limit }) => Some((scope, limit)),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_burst_then_refill() {
+        let mut limiter = limiter();
+        let start = Instant::now();
+
+        for _ in 0..4 {
+            assert_eq!(limit(limiter.check("math", 10, start)), None);
+        }
+        assert_eq!(
+            limit(limiter.check("math", 10, start)),
+            Some(("agent", "messages per second"))
+        );
+
+        // Two messages per second come back, and no more than the burst
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limit(limiter.check("math", 10, later)), None);
+        assert!(limit(limiter.check("math", 10, later)).is_some());
+
+        let much_later = start + Duration::from_secs(60);
+        for _ in 0..4 {
+            assert_eq!(limit(limiter.check("math", 10, much_later)), None);
+        }
+        assert!(limit(limiter.check("math", 10, much_later)).is_some());
+    }
+
+    #[test]
+    fn test_room_and_byte_limits() {
+        let mut limiter = limiter();
+        let now = Instant::now();
+
+        // 600 bytes per minute per agent; a rejected message costs nothing
+        assert_eq!(limit(limiter.check("math", 500, now)), None);
+        assert_eq!(
+            limit(limiter.check("math", 200, now)),
+            Some(("agent", "bytes per minute"))
+        );
+        assert_eq!(limit(limiter.check("math", 100, now)), None);
+
+        // The room's burst of 6 is shared between agents
+        for _ in 0..4 {
+            assert_eq!(limit(limiter.check("cmd", 1, now)), None);
+        }
+        assert_eq!(
+            limit(limiter.check("docs", 1, now)),
+            Some(("room", "messages per second"))
+        );
+    }
+
+    #[test]
+    fn test_idle_agents_evicted() {
+        let mut limiter = limiter();
+        let start = Instant::now();
+
+        limiter.check("math", 10, start).unwrap();
+        limiter.check("cmd", 600, start).unwrap();
+        assert!(!limiter.evict_idle(start));
+        assert_eq!(limiter.agents.len(), 2);
+
+        // Messages refill within a second, but cmd's bytes take a minute
+        let later = start + Duration::from_secs(2);
+        assert!(!limiter.evict_idle(later));
+        assert_eq!(limiter.agents.len(), 1);
+        assert!(limiter.agents.contains_key("cmd"));
+
+        assert!(limiter.evict_idle(start + Duration::from_secs(61)));
+        assert!(limiter.agents.is_empty());
+    }
+}
@@ -0,0 +1,186 @@
+//! Mic grant persistence
+//!
+//! Grants and their `messages_sent` counters are written to a JSON file after
+//! every change and reloaded on startup, so a gateway restart doesn't reject
+//! in-flight agents with `no_mic_grant`.
+
+use crate::mic_grant::MicGrantTracker;
+use common::MicGrantState;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// On-disk layout: grants keyed by room ID
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StateFile {
+    rooms: BTreeMap<String, Vec<MicGrantState>>,
+}
+
+/// JSON file holding every room's mic grants
+#[derive(Debug)]
+pub struct GrantStore {
+    path: PathBuf,
+}
+
+impl GrantStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Load saved grants, dropping any that expired before `current_ts`
+    ///
+    /// A missing file is a fresh start; an unreadable one is logged and
+    /// ignored rather than keeping the gateway down.
+    pub fn load(&self, current_ts: u64) -> HashMap<String, MicGrantTracker> {
+        let state: StateFile = match std::fs::read(&self.path) {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(state) => state,
+                Err(e) => {
+                    warn!("Ignoring corrupt state file {}: {}", self.path.display(), e);
+                    return HashMap::new();
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return HashMap::new(),
+            Err(e) => {
+                warn!("Failed to read state file {}: {}", self.path.display(), e);
+                return HashMap::new();
+            }
+        };
+
+        let mut trackers = HashMap::new();
+        for (room_id, grants) in state.rooms {
+            let saved = grants.len();
+            let tracker = MicGrantTracker::from_grants(
+                grants
+                    .into_iter()
+                    .filter(|g| g.grant.expires_at >= current_ts),
+            );
+            info!(
+                "Restored {} mic grant(s) for room {} ({} expired)",
+                tracker.len(),
+                room_id,
+                saved - tracker.len()
+            );
+            if !tracker.is_empty() {
+                trackers.insert(room_id, tracker);
+            }
+        }
+        trackers
+    }
+
+    /// Write all grants, replacing the file atomically
+    ///
+    /// The new state is synced to a temporary file that is then renamed over
+    /// the old one, so a crash mid-write leaves the previous grants in place.
+    pub fn save(&self, trackers: &HashMap<String, MicGrantTracker>) -> io::Result<()> {
+        let state = StateFile {
+            rooms: trackers
+                .iter()
+                .filter(|(_, tracker)| !tracker.is_empty())
+                .map(|(room_id, tracker)| (room_id.clone(), tracker.grants().cloned().collect()))
+                .collect(),
+        };
+        let json = serde_json::to_vec_pretty(&state)?;
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{MicGrantPayload, ResultMessageType};
+
+    fn state(agent_id: &str, expires_at: u64, messages_sent: u32) -> MicGrantState {
+        MicGrantState {
+            grant: MicGrantPayload {
+                task_id: "task_1".to_string(),
+                agent_id: agent_id.to_string(),
+                max_messages: 5,
+                allowed_message_types: vec![ResultMessageType::Result],
+                expires_at,
+            },
+            messages_sent,
+            sent_by: Default::default(),
+        }
+    }
+
+    fn store(dir: &tempfile::TempDir) -> GrantStore {
+        GrantStore::new(dir.path().join("gateway-state.json"))
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(&dir);
+        let trackers = HashMap::from([
+            (
+                "ops".to_string(),
+                MicGrantTracker::from_grants([state("math", 200, 2), state("cmd", 200, 0)]),
+            ),
+            ("empty".to_string(), MicGrantTracker::default()),
+        ]);
+        store.save(&trackers).unwrap();
+
+        let loaded = store.load(100);
+        assert_eq!(loaded.len(), 1);
+        let ops = &loaded["ops"];
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops.get("math", "task_1").unwrap().messages_sent, 2);
+        assert_eq!(ops.get("cmd", "task_1").unwrap().messages_sent, 0);
+
+        // Nothing is left behind from the temporary file
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_load_prunes_expired_grants() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(&dir);
+        let trackers = HashMap::from([
+            (
+                "ops".to_string(),
+                MicGrantTracker::from_grants([state("math", 99, 1), state("cmd", 100, 1)]),
+            ),
+            (
+                "stale".to_string(),
+                MicGrantTracker::from_grants([state("math", 50, 0)]),
+            ),
+        ]);
+        store.save(&trackers).unwrap();
+
+        let loaded = store.load(100);
+        assert_eq!(loaded.len(), 1);
+        let ops = &loaded["ops"];
+        assert_eq!(ops.len(), 1);
+        assert!(ops.get("math", "task_1").is_none());
+        assert!(ops.get("cmd", "task_1").is_some());
+    }
+
+    #[test]
+    fn test_missing_or_corrupt_file_starts_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store(&dir);
+        assert!(store.load(0).is_empty());
+
+        for corrupt in [&b"not json"[..], b"", br#"{"rooms":{"ops":[{"task_id":"#] {
+            std::fs::write(&store.path, corrupt).unwrap();
+            assert!(store.load(0).is_empty());
+        }
+
+        // A corrupt file is overwritten by the next save
+        let trackers = HashMap::from([(
+            "ops".to_string(),
+            MicGrantTracker::from_grants([state("math", 200, 0)]),
+        )]);
+        store.save(&trackers).unwrap();
+        assert_eq!(store.load(100)["ops"].len(), 1);
+    }
+}
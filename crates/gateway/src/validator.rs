@@ -1,28 +1,106 @@
 //! Message validation logic
 
+use crate::content::{self, ContentLimits};
 use crate::mic_grant::{MicGrantTracker, ValidationError};
-use common::{Envelope, EnvelopeType};
+use crate::rate_limit::RateLimiter;
+use common::{Envelope, Payload};
+use std::time::{Duration, Instant};
+
+/// Time allowed for validating one candidate
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    at: Option<Instant>,
+    budget_ms: u64,
+}
+
+impl Deadline {
+    /// Start a `budget_ms` budget now; a budget of 0, or one too large to
+    /// represent, never runs out
+    pub fn start(budget_ms: u64) -> Self {
+        let at = match budget_ms {
+            0 => None,
+            _ => Instant::now().checked_add(Duration::from_millis(budget_ms)),
+        };
+        Self { at, budget_ms }
+    }
+
+    /// Fail once the budget is spent
+    pub fn check(&self) -> Result<(), ValidationError> {
+        match self.at {
+            Some(at) if Instant::now() > at => Err(ValidationError::ValidationTimeout {
+                budget_ms: self.budget_ms,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
 
 /// Validate a candidate message for publication
+///
+/// The message still has to be counted against its grant once every other
+/// check has passed.
 pub fn validate_message(
     envelope: &Envelope,
-    tracker: &mut MicGrantTracker,
+    room_id: &str,
+    tracker: &MicGrantTracker,
+    limiter: &mut RateLimiter,
+    limits: &ContentLimits,
+    deadline: &Deadline,
     current_ts: u64,
 ) -> Result<(), ValidationError> {
-    // Must be a result message
-    if envelope.message_type != EnvelopeType::Result {
-        return Err(ValidationError::MessageTypeNotAllowed);
+    // Must be addressed to the room it was published in
+    if envelope.room_id != room_id {
+        return Err(ValidationError::RoomMismatch {
+            envelope_room: envelope.room_id.clone(),
+        });
     }
 
-    // Extract result payload
-    let result_payload = serde_json::from_value::<common::ResultPayload>(envelope.payload.clone())
-        .map_err(|_| ValidationError::MessageTypeNotAllowed)?;
+    // Every candidate counts against the rate limits, granted or not
+    let size = serde_json::to_vec(envelope).map(|v| v.len()).unwrap_or(0);
+    limiter.check(&envelope.from.id, size, Instant::now())?;
+
+    // Must be a result message
+    let Payload::Result(result_payload) = &envelope.payload else {
+        let envelope_type = serde_json::to_value(envelope.message_type())
+            .ok()
+            .and_then(|v| v.as_str().map(String::from))
+            .unwrap_or_default();
+        return Err(ValidationError::MessageTypeNotAllowed {
+            message_type: envelope_type,
+            allowed: vec!["result".to_string()],
+        });
+    };
+
+    // Content must be well-formed for its message type
+    content::validate_content(result_payload, limits)?;
+
+    // Out of time: stop before looking up the grant
+    deadline.check()?;
 
     // Validate against mic grant
     tracker.validate(
         &envelope.from.id,
         &result_payload.task_id,
-        &result_payload.message_type.to_string(),
+        result_payload.message_type.clone(),
         current_ts,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        assert!(Deadline::start(0).check().is_ok());
+        // Too far out to represent, so unlimited rather than a panic
+        assert!(Deadline::start(u64::MAX).check().is_ok());
+
+        let deadline = Deadline::start(1);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(matches!(
+            deadline.check(),
+            Err(ValidationError::ValidationTimeout { budget_ms: 1 })
+        ));
+    }
+}
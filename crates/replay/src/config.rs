@@ -1,4 +1,5 @@
 use clap::Parser;
+use common::RoomId;
 
 #[derive(Parser, Debug)]
 #[command(name = "replay")]
@@ -14,9 +15,18 @@ pub struct ReplayConfig {
 
     /// Room ID
     #[arg(long, env = "AOR_ROOM_ID")]
-    pub room_id: String,
+    pub room_id: RoomId,
 
     /// Input file path (JSONL from sink)
     #[arg(long, env = "AOR_REPLAY_FILE", default_value = "messages.jsonl")]
     pub input_file: String,
+
+    /// Only load messages from this conversation thread (correlation ID)
+    #[arg(long, env = "AOR_REPLAY_THREAD")]
+    pub thread: Option<String>,
+
+    /// Also replay control and moderation messages (mic grants, revokes,
+    /// moderation decisions), which running gateways act on
+    #[arg(long, env = "AOR_REPLAY_CONTROL", default_value = "false")]
+    pub replay_control: bool,
 }
@@ -6,7 +6,7 @@ mod config;
 mod tui;
 
 use clap::Parser;
-use common::Envelope;
+use common::{topics, Envelope, Payload};
 use config::ReplayConfig;
 use rumqttc::{AsyncClient, MqttOptions, QoS};
 use std::io::{BufRead, BufReader};
@@ -23,11 +23,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("  Room ID: {}", config.room_id);
     info!("  MQTT: {}:{}", config.mqtt_host, config.mqtt_port);
     info!("  Input file: {}", config.input_file);
+    info!("  Replay control: {}", config.replay_control);
 
     // Load messages from file
-    let messages = load_messages_from_file(&config.input_file)?;
+    let mut messages = load_messages_from_file(&config.input_file)?;
     info!("Loaded {} messages", messages.len());
 
+    if let Some(thread) = &config.thread {
+        messages.retain(|msg| msg.thread_id() == thread);
+        info!("  Thread {}: {} messages", thread, messages.len());
+    }
+
     // Set up MQTT client
     let mut mqtt_opts = MqttOptions::new("replay", &config.mqtt_host, config.mqtt_port);
     mqtt_opts.set_keep_alive(std::time::Duration::from_secs(5));
@@ -50,14 +56,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Handle replay commands
     let replay_client = client.clone();
     let replay_room_id = config.room_id.clone();
+    let replay_control = config.replay_control;
     let replay_handle = tokio::spawn(async move {
         while let Some(cmd) = replay_rx.recv().await {
             match cmd {
                 tui::TuiCommand::Replay(messages) => {
                     info!("Replaying {} messages", messages.len());
                     for msg in messages {
-                        // Republish to public topic
-                        let topic = common::topics::public(&replay_room_id);
+                        let Some(topic) = replay_topic(&replay_room_id, &msg, replay_control)
+                        else {
+                            info!(
+                                "Skipped {:?} message {} (needs --replay-control)",
+                                msg.message_type(),
+                                msg.id
+                            );
+                            continue;
+                        };
                         if let Ok(payload) = serde_json::to_vec(&msg) {
                             if let Err(e) = replay_client
                                 .publish(topic, QoS::AtLeastOnce, false, payload)
@@ -85,16 +99,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Topic a recorded message is republished to, based on its type
+///
+/// Gateways apply grants, revokes, snapshots and moderation decisions they see
+/// on the control, mic grant and moderation topics, so replaying those would
+/// re-grant expired mics and re-decide held messages; they're skipped unless
+/// `control` is set.
+fn replay_topic(room_id: &str, msg: &Envelope, control: bool) -> Option<String> {
+    let topic = match msg.payload {
+        Payload::MicGrant(_)
+        | Payload::MicRevoke(_)
+        | Payload::Reject(_)
+        | Payload::Redaction(_)
+        | Payload::MicExpired(_) => topics::control(room_id),
+        Payload::MicGrantSnapshot(_) => topics::mic_grants(room_id),
+        Payload::ModerationRequest(_) | Payload::ModerationDecision(_) => {
+            topics::moderation(room_id)
+        }
+        Payload::Summary(_) => return Some(topics::summary(room_id)),
+        _ => return Some(topics::public(room_id)),
+    };
+    control.then_some(topic)
+}
+
 fn load_messages_from_file(path: &str) -> Result<Vec<Envelope>, Box<dyn std::error::Error>> {
     let file = std::fs::File::open(path)?;
     let reader = BufReader::new(file);
     let mut messages = Vec::new();
 
-    for line in reader.lines() {
-        if let Ok(line) = line {
-            if let Ok(envelope) = serde_json::from_str::<Envelope>(&line) {
-                messages.push(envelope);
-            }
+    for (line_no, line) in reader.lines().map_while(Result::ok).enumerate() {
+        match Envelope::from_slice(line.as_bytes()) {
+            Ok(envelope) => messages.push(envelope),
+            Err(e) => tracing::warn!("Skipping line {}: {}", line_no + 1, e),
         }
     }
 
@@ -21,7 +21,9 @@ pub enum TuiCommand {
 }
 
 struct TuiState {
+    all_messages: Vec<Envelope>,
     messages: Vec<Envelope>,
+    thread: Option<String>,
     selected: usize,
     scroll: usize,
     status: String,
@@ -30,13 +32,32 @@ struct TuiState {
 impl TuiState {
     fn new(messages: Vec<Envelope>) -> Self {
         Self {
+            all_messages: messages.clone(),
             messages,
+            thread: None,
             selected: 0,
             scroll: 0,
             status: "Press '?' for help".to_string(),
         }
     }
 
+    /// Narrow the list to the selected message's thread, or back to all messages
+    fn toggle_thread(&mut self) {
+        if self.thread.take().is_some() {
+            self.messages = self.all_messages.clone();
+        } else if let Some(msg) = self.get_selected() {
+            let thread = msg.thread_id().to_string();
+            self.messages = self
+                .all_messages
+                .iter()
+                .filter(|m| m.thread_id() == thread)
+                .cloned()
+                .collect();
+            self.thread = Some(thread);
+        }
+        self.select_first();
+    }
+
     fn select_next(&mut self) {
         if !self.messages.is_empty() && self.selected < self.messages.len() - 1 {
             self.selected += 1;
@@ -106,6 +127,13 @@ pub async fn run_tui(
                             let _ = replay_tx.send(TuiCommand::Replay(vec![msg_clone]));
                         }
                     }
+                    KeyCode::Char('t') => {
+                        state.toggle_thread();
+                        state.status = match &state.thread {
+                            Some(thread) => format!("Showing thread {}", thread),
+                            None => "Showing all messages".to_string(),
+                        };
+                    }
                     KeyCode::Char('R') => {
                         let count = state.messages.len();
                         let messages = state.messages.clone();
@@ -114,7 +142,7 @@ pub async fn run_tui(
                     }
                     KeyCode::Char('?') => {
                         state.status =
-                            "j/k:nav | r:replay | R:replay all | g/G:top/bottom | q:quit"
+                            "j/k:nav | r:replay | R:replay all | t:thread | g/G:top/bottom | q:quit"
                                 .to_string();
                     }
                     _ => {}
@@ -147,12 +175,19 @@ fn ui(f: &mut Frame, state: &TuiState) {
         .split(f.area());
 
     // Header
-    let header = Paragraph::new(format!("Replay - {} messages loaded", state.messages.len()))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Archive Browser"),
-        );
+    let title = match &state.thread {
+        Some(thread) => format!(
+            "Replay - {} messages in thread {}",
+            state.messages.len(),
+            thread
+        ),
+        None => format!("Replay - {} messages loaded", state.messages.len()),
+    };
+    let header = Paragraph::new(title).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Archive Browser"),
+    );
     f.render_widget(header, chunks[0]);
 
     // Message list
@@ -163,7 +198,7 @@ fn ui(f: &mut Frame, state: &TuiState) {
         .skip(state.scroll)
         .take(chunks[1].height as usize)
         .map(|(i, msg)| {
-            let ts = DateTime::<Utc>::from_timestamp(msg.ts as i64, 0)
+            let ts = DateTime::<Utc>::from_timestamp_millis(msg.ts as i64)
                 .map(|dt| dt.format("%H:%M:%S").to_string())
                 .unwrap_or_else(|| "??:??:??".to_string());
 
@@ -180,7 +215,7 @@ fn ui(f: &mut Frame, state: &TuiState) {
                     Style::default().fg(sender_color),
                 ),
                 Span::styled(
-                    format!("{:?}", msg.message_type),
+                    format!("{:?}", msg.message_type()),
                     Style::default().fg(Color::White),
                 ),
             ]);
@@ -202,13 +237,19 @@ fn ui(f: &mut Frame, state: &TuiState) {
 
     // Detail view
     if let Some(msg) = state.get_selected() {
-        let detail_text = if let Ok(json) = serde_json::to_string_pretty(&msg.payload) {
+        // Payload serializes as {"type": ..., "payload": ...}; show only the body
+        let body = serde_json::to_value(&msg.payload)
+            .map(|v| v["payload"].clone())
+            .and_then(|v| serde_json::to_string_pretty(&v));
+        let detail_text = if let Ok(json) = body {
             format!(
-                "ID: {}\nFrom: {} ({})\nType: {:?}\nTimestamp: {}\n\nPayload:\n{}",
+                "ID: {}\nThread: {} (caused by {})\nFrom: {} ({:?})\nType: {:?}\nTimestamp: {}\n\nPayload:\n{}",
                 msg.id,
+                msg.thread_id(),
+                msg.causation_id.as_deref().unwrap_or("-"),
                 msg.from.id,
-                format!("{:?}", msg.from.kind),
-                msg.message_type,
+                msg.from.kind,
+                msg.message_type(),
                 msg.ts,
                 json
             )
@@ -1,6 +1,7 @@
 //! Sink configuration
 
 use clap::Parser;
+use common::RoomId;
 
 #[derive(Debug, Parser)]
 #[command(name = "sink")]
@@ -16,7 +17,11 @@ pub struct SinkConfig {
 
     /// Room ID
     #[arg(long, env = "AOR_ROOM_ID", default_value = "default")]
-    pub room_id: String,
+    pub room_id: RoomId,
+
+    /// Serve every room via `rooms/+/...` subscriptions instead of only --room-id
+    #[arg(long, env = "AOR_ALL_ROOMS", default_value = "false")]
+    pub all_rooms: bool,
 
     /// Output file path
     #[arg(long, env = "AOR_SINK_FILE", default_value = "messages.jsonl")]
@@ -25,4 +30,9 @@ pub struct SinkConfig {
     /// Append to existing file (default: true)
     #[arg(long, env = "AOR_SINK_APPEND", default_value = "true")]
     pub append: bool,
+
+    /// Also record control and moderation messages (mic grants, revocations,
+    /// rejections, moderator decisions) so archived threads are complete
+    #[arg(long, env = "AOR_SINK_RECORD_CONTROL", default_value = "false")]
+    pub record_control: bool,
 }
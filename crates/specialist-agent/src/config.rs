@@ -1,6 +1,7 @@
 //! Specialist Agent configuration
 
 use clap::Parser;
+use common::{AgentId, RoomId};
 
 #[derive(Debug, Parser)]
 #[command(name = "specialist-agent")]
@@ -24,11 +25,11 @@ pub struct AgentConfig {
 
     /// Room ID
     #[arg(long, env = "AOR_ROOM_ID", default_value = "default")]
-    pub room_id: String,
+    pub room_id: RoomId,
 
     /// Agent ID (unique identifier for this agent)
     #[arg(long, env = "AOR_AGENT_ID", default_value = "agent")]
-    pub agent_id: String,
+    pub agent_id: AgentId,
 
     /// OpenAI API key
     #[arg(long, env = "AOR_OPENAI_API_KEY")]
@@ -49,4 +50,17 @@ pub struct AgentConfig {
     /// Maximum messages to keep in conversation memory
     #[arg(long, env = "AOR_MAX_MEMORY_MESSAGES", default_value = "50")]
     pub max_memory_messages: usize,
+
+    /// Base64 Ed25519 secret key file used to sign envelopes sent by this agent
+    #[arg(long, env = "AOR_SIGNING_KEY")]
+    pub signing_key: Option<String>,
+
+    /// JSON file of senders' base64 Ed25519 public keys; when set, inbox tasks
+    /// must be signed by the task issuer
+    #[arg(long, env = "AOR_KEY_REGISTRY")]
+    pub key_registry: Option<String>,
+
+    /// Sender ID whose signed tasks are accepted when a key registry is set
+    #[arg(long, env = "AOR_TASK_ISSUER", default_value = "facilitator")]
+    pub task_issuer: String,
 }
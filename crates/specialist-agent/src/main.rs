@@ -15,23 +15,22 @@ mod llm;
 
 use clap::Parser;
 use common::message::{
-    AckContent, Envelope, EnvelopeType, FindingContent, HeartbeatPayload, ResultContent,
-    ResultMessageType, ResultOutcome, ResultPayload, Sender, SenderKind, TaskPayload,
+    AckContent, Envelope, FindingContent, Payload, ResultContent, ResultOutcome, Sender,
 };
-use common::{topics, MessageHistory};
+use common::runtime::{Component, Context, Identity, MqttSettings, Runtime};
+use common::{topics, EnvelopeBuilder, KeyRegistry, MessageHistory, Signer, Topic};
 use config::AgentConfig;
 use llm::SpecialistLlm;
-use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::process::Command;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+const DESCRIPTION: &str = "Command execution agent. Can run shell commands (bash/zsh) and return their output. Ask me to check system status, run scripts, or execute any command-line operations.";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
 
-    let config = Arc::new(AgentConfig::parse());
+    let config = AgentConfig::parse();
 
     info!("Command Execution Agent starting...");
     info!("  Room ID: {}", config.room_id);
@@ -48,100 +47,140 @@ When a user asks you to run a command, check something on the system, or perform
 3. Present the results clearly to the user
 
 Be helpful and explain what commands you're running and why.".to_string();
-    let llm_client = Arc::new(SpecialistLlm::new(
+    let llm_client = SpecialistLlm::new(
         config.openai_api_key.clone(),
         config.openai_model.clone(),
         config.openai_base_url.clone(),
         system_prompt,
-    ));
-
-    // Initialize MQTT client
-    let mut mqttoptions = MqttOptions::new(
-        format!("{}-{}", config.mqtt_client_id_prefix, config.agent_id),
-        &config.mqtt_host,
-        config.mqtt_port,
     );
-    mqttoptions.set_keep_alive(std::time::Duration::from_secs(config.mqtt_keep_alive_secs));
-    let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
-
-    // Subscribe to topics
-    let public_topic = topics::public(&config.room_id);
-    let control_topic = topics::control(&config.room_id);
-    let inbox_topic = topics::agent_inbox(&config.room_id, &config.agent_id);
-
-    client.subscribe(&public_topic, QoS::AtLeastOnce).await?;
-    client.subscribe(&control_topic, QoS::AtLeastOnce).await?;
-    client.subscribe(&inbox_topic, QoS::AtLeastOnce).await?;
-
-    info!("Subscribed to:");
-    info!("  {}", public_topic);
-    info!("  {}", control_topic);
-    info!("  {}", inbox_topic);
-
-    // Initialize conversation memory
-    let memory = Arc::new(tokio::sync::Mutex::new(MessageHistory::new(
-        config.max_memory_messages,
-    )));
-
-    // Specific Initializers
-    let heartbeat_client = client.clone();
-    let heartbeat_room_id = config.room_id.clone();
-    let heartbeat_agent_id = config.agent_id.clone();
-    tokio::spawn(async move {
-        send_heartbeats(heartbeat_client, &heartbeat_room_id, &heartbeat_agent_id).await;
-    });
+
+    let mut identity = Identity::new(
+        &config.room_id,
+        Sender::agent(&config.agent_id),
+        DESCRIPTION,
+    )
+    .accepting_tasks();
+    if let Some(path) = &config.signing_key {
+        let signer = Signer::load(config.agent_id.as_str(), path)?;
+        info!(
+            "  Signing key: {} (public key {})",
+            path,
+            signer.public_key()
+        );
+        identity = identity.signed_with(signer);
+    }
+    let keys = match &config.key_registry {
+        Some(path) => {
+            let keys = KeyRegistry::load(path)?;
+            info!("  Key registry: {} key(s) from {}", keys.len(), path);
+            Some(keys)
+        }
+        None => None,
+    };
+    let mqtt = MqttSettings {
+        host: config.mqtt_host.clone(),
+        port: config.mqtt_port,
+        client_id: format!("{}-{}", config.mqtt_client_id_prefix, config.agent_id),
+        keep_alive_secs: config.mqtt_keep_alive_secs,
+    };
+
+    let agent = SpecialistAgent {
+        agent_id: config.agent_id.to_string(),
+        public_topic: topics::public(&config.room_id),
+        control_topic: topics::control(&config.room_id),
+        inbox_topic: topics::agent_inbox(&config.room_id, &config.agent_id),
+        // Initialize conversation memory
+        memory: MessageHistory::new(config.max_memory_messages),
+        llm_client,
+        keys,
+        task_issuer: config.task_issuer.clone(),
+    };
 
     info!("Command Execution Agent running");
 
-    // Main event loop
-    loop {
-        match eventloop.poll().await {
-            Ok(Event::Incoming(Packet::Publish(p))) => {
-                if p.topic == inbox_topic {
-                    handle_inbox_message(&p.payload, &client, &config, &llm_client, &memory).await;
-                } else if p.topic == public_topic {
-                    handle_public_message(&p.payload, &memory).await;
-                } else if p.topic == control_topic {
-                    debug!("Received control message");
+    Runtime::new(identity, mqtt).run(agent).await?;
+    Ok(())
+}
+
+struct SpecialistAgent {
+    agent_id: String,
+    public_topic: String,
+    control_topic: String,
+    inbox_topic: String,
+    memory: MessageHistory,
+    llm_client: SpecialistLlm,
+    /// Registered public keys, when signatures are required
+    keys: Option<KeyRegistry>,
+    /// The only sender whose tasks are accepted when signatures are required
+    task_issuer: String,
+}
+
+impl Component for SpecialistAgent {
+    fn subscriptions(&self) -> Vec<String> {
+        vec![
+            self.public_topic.clone(),
+            self.control_topic.clone(),
+            self.inbox_topic.clone(),
+        ]
+    }
+
+    async fn on_envelope(&mut self, ctx: &Context, topic: &str, envelope: Envelope) {
+        match Topic::parse(topic) {
+            Ok(Topic::Inbox { .. }) => {
+                if let Some(keys) = &self.keys {
+                    // Any registered key verifies its own envelopes, so pin
+                    // the signer to the issuer before checking the signature
+                    if envelope.from.id != self.task_issuer {
+                        warn!(
+                            "Ignoring inbox message {} from {}: not the task issuer {}",
+                            envelope.id, envelope.from.id, self.task_issuer
+                        );
+                        return;
+                    }
+                    if let Err(e) = keys.verify(&envelope) {
+                        warn!(
+                            "Ignoring inbox message {} from {}: {}",
+                            envelope.id, envelope.from.id, e
+                        );
+                        return;
+                    }
                 }
+                handle_inbox_message(envelope, ctx, &self.llm_client, &self.memory).await
             }
+            Ok(Topic::Public { .. }) => self.memory.add(envelope),
+            Ok(Topic::Control { .. }) => match &envelope.payload {
+                Payload::Redaction(notice) if notice.agent_id == self.agent_id => {
+                    let changes: Vec<String> = notice
+                        .redactions
+                        .iter()
+                        .map(|r| format!("{} {} by {}", r.field, r.action, r.rule))
+                        .collect();
+                    warn!(
+                        "Gateway redacted message {} for {}: {}",
+                        notice.message_id,
+                        notice.task_id,
+                        changes.join(", ")
+                    );
+                }
+                _ => debug!("Received control message"),
+            },
             Ok(_) => {}
-            Err(e) => {
-                error!("MQTT error: {}", e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            }
+            Err(e) => warn!("{}", e),
         }
     }
 }
 
-async fn handle_public_message(payload: &[u8], memory: &Arc<tokio::sync::Mutex<MessageHistory>>) {
-    if let Ok(envelope) = serde_json::from_slice::<Envelope>(payload) {
-        let mut mem = memory.lock().await;
-        mem.add(envelope);
-    }
-}
-
 async fn handle_inbox_message(
-    payload: &[u8],
-    client: &AsyncClient,
-    config: &AgentConfig,
+    envelope: Envelope,
+    ctx: &Context,
     llm_client: &SpecialistLlm,
-    memory: &Arc<tokio::sync::Mutex<MessageHistory>>,
+    memory: &MessageHistory,
 ) {
-    let Ok(envelope) = serde_json::from_slice::<Envelope>(payload) else {
-        return;
-    };
+    // Results for this task are threaded under the task envelope
+    let envelopes = ctx.envelopes().caused_by(&envelope);
 
-    if envelope.message_type != EnvelopeType::Task {
+    let Payload::Task(task_payload) = envelope.payload else {
         return;
-    }
-
-    let task_payload = match serde_json::from_value::<TaskPayload>(envelope.payload.clone()) {
-        Ok(p) => p,
-        Err(e) => {
-            error!("Failed to parse task payload: {}", e);
-            return;
-        }
     };
 
     info!(
@@ -151,10 +190,9 @@ async fn handle_inbox_message(
 
     // Send acknowledgment
     send_result(
-        client,
-        config,
+        ctx,
+        &envelopes,
         &task_payload.task_id,
-        ResultMessageType::Ack,
         ResultContent::Ack(AckContent {
             text: "Task received, processing...".to_string(),
         }),
@@ -162,10 +200,7 @@ async fn handle_inbox_message(
     .await;
 
     // Get conversation context from memory
-    let mut context = {
-        let mem = memory.lock().await;
-        mem.to_chat_messages()
-    };
+    let mut context = memory.to_chat_messages();
 
     // Agentic loop: keep executing until no tool calls are made
     let final_result = loop {
@@ -207,10 +242,9 @@ async fn handle_inbox_message(
 
                     // Send finding with command being executed
                     send_result(
-                        client,
-                        config,
+                        ctx,
+                        &envelopes,
                         &task_payload.task_id,
-                        ResultMessageType::Finding,
                         ResultContent::Finding(FindingContent {
                             text: Some(format!("🔧 Executing: {}", command_str)),
                             bullets: None,
@@ -235,10 +269,9 @@ async fn handle_inbox_message(
 
                             // Send finding with execution details
                             send_result(
-                                client,
-                                config,
+                                ctx,
+                                &envelopes,
                                 &task_payload.task_id,
-                                ResultMessageType::Finding,
                                 ResultContent::Finding(FindingContent {
                                     text: Some(format!("Exit code: {}", exit_code)),
                                     bullets: None,
@@ -309,10 +342,9 @@ async fn handle_inbox_message(
 
     // Send the final result
     send_result(
-        client,
-        config,
+        ctx,
+        &envelopes,
         &task_payload.task_id,
-        ResultMessageType::Result,
         ResultContent::Result(ResultOutcome { text: final_result }),
     )
     .await;
@@ -321,99 +353,18 @@ async fn handle_inbox_message(
 }
 
 async fn send_result(
-    client: &AsyncClient,
-    config: &AgentConfig,
+    ctx: &Context,
+    envelopes: &EnvelopeBuilder,
     task_id: &str,
-    message_type: ResultMessageType,
     content: ResultContent,
 ) {
-    let ts = now_secs();
+    let message_type = content.message_type();
+    let envelope = envelopes.result(task_id, content);
 
-    let result_payload = ResultPayload {
-        task_id: task_id.to_string(),
-        message_type: message_type.clone(),
-        content,
-    };
-
-    let envelope = Envelope {
-        id: format!("result_{}_{}", task_id, ts),
-        message_type: EnvelopeType::Result,
-        room_id: config.room_id.clone(),
-        from: Sender {
-            kind: SenderKind::Agent,
-            id: config.agent_id.clone(),
-        },
-        ts,
-        payload: serde_json::to_value(result_payload).unwrap(),
-    };
-
-    let topic = topics::public_candidates(&config.room_id);
-    let payload_bytes = serde_json::to_vec(&envelope).unwrap();
-
-    if let Err(e) = client
-        .publish(topic, QoS::AtLeastOnce, false, payload_bytes)
-        .await
-    {
+    let topic = topics::public_candidates(ctx.room_id());
+    if let Err(e) = ctx.publish(topic, &envelope).await {
         error!("Failed to send result: {}", e);
     } else {
         info!("Sent {} for task {}", message_type, task_id);
     }
 }
-
-async fn send_heartbeats(client: AsyncClient, room_id: &str, agent_id: &str) {
-    let mut counter = 0u64;
-    let description = "Command execution agent. Can run shell commands (bash/zsh) and return their output. Ask me to check system status, run scripts, or execute any command-line operations.";
-
-    loop {
-        counter += 1;
-        let ts = now_secs();
-
-        // Send description every 3rd heartbeat
-        let payload = if counter % 3 == 0 {
-            HeartbeatPayload {
-                ts,
-                description: Some(description.to_string()),
-                can_accept_tasks: true,
-            }
-        } else {
-            HeartbeatPayload {
-                ts,
-                description: None,
-                can_accept_tasks: true,
-            }
-        };
-
-        let envelope = Envelope {
-            id: format!("heartbeat_{}_{}", agent_id, counter),
-            message_type: EnvelopeType::Heartbeat,
-            room_id: room_id.to_string(),
-            from: Sender {
-                kind: SenderKind::Agent,
-                id: agent_id.to_string(),
-            },
-            ts,
-            payload: serde_json::to_value(payload).unwrap(),
-        };
-
-        let topic = topics::agent_heartbeat(room_id, agent_id);
-        let payload_bytes = serde_json::to_vec(&envelope).unwrap();
-
-        if let Err(e) = client
-            .publish(topic, QoS::AtLeastOnce, false, payload_bytes)
-            .await
-        {
-            error!("Failed to send heartbeat: {}", e);
-        } else {
-            debug!("Sent heartbeat #{}", counter);
-        }
-
-        tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
-    }
-}
-
-fn now_secs() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-}
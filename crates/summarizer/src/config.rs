@@ -1,6 +1,7 @@
 //! Summarizer configuration
 
 use clap::Parser;
+use common::RoomId;
 
 #[derive(Debug, Parser)]
 #[command(name = "summarizer")]
@@ -32,7 +33,11 @@ pub struct SummarizerConfig {
 
     /// Room ID
     #[arg(long, env = "AOR_ROOM_ID", default_value = "default")]
-    pub room_id: String,
+    pub room_id: RoomId,
+
+    /// Serve every room via `rooms/+/...` subscriptions instead of only --room-id
+    #[arg(long, env = "AOR_ALL_ROOMS", default_value = "false")]
+    pub all_rooms: bool,
 
     /// Number of messages before generating a summary
     #[arg(long, env = "AOR_SUMMARY_INTERVAL", default_value = "3")]
@@ -9,7 +9,11 @@ pub struct SummarizerLlm {
 impl SummarizerLlm {
     pub fn new(api_key: String, model: String, base_url: Option<String>) -> Self {
         Self {
-            client: LlmClient::new(api_key, model, base_url.unwrap_or_else(|| "https://api.openai.com/v1".to_string())),
+            client: LlmClient::new(
+                api_key,
+                model,
+                base_url.unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
+            ),
         }
     }
 
@@ -54,7 +58,7 @@ impl SummarizerLlm {
             .client
             .complete(messages, Some(0.3))
             .await
-            .map_err(|e| AorError::Llm(e))?;
+            .map_err(AorError::Llm)?;
 
         Ok(summary)
     }
@@ -7,16 +7,14 @@ mod config;
 mod llm;
 
 use clap::Parser;
-use common::message::HeartbeatPayload;
+use common::runtime::{Component, Context, Identity, MqttSettings, Runtime};
+use common::time::now_secs;
 use common::{
-    topics, Envelope, EnvelopeType, MessageHistory, ResultPayload, Sender, SenderKind,
-    SummaryPayload,
+    topics, Envelope, MessageHistory, Payload, ResultMessageType, Sender, SummaryPayload, Topic,
 };
 use config::SummarizerConfig;
 use llm::SummarizerLlm;
-use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::collections::HashMap;
 use tracing::{error, info, warn};
 
 #[tokio::main]
@@ -27,176 +25,134 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Summarizer starting");
     info!("  Room ID: {}", config.room_id);
+    info!("  All rooms: {}", config.all_rooms);
     info!("  MQTT: {}:{}", config.mqtt_host, config.mqtt_port);
     info!("  Summary interval: {} messages", config.summary_interval);
     info!("  LLM: {}", config.openai_model);
 
-    // Connect to MQTT
-    let mut mqtt_options = MqttOptions::new("summarizer", &config.mqtt_host, config.mqtt_port);
-    mqtt_options.set_keep_alive(std::time::Duration::from_secs(30));
-    let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
-
-    // Subscribe to public topic
-    let public_topic = topics::public(&config.room_id);
-    client.subscribe(&public_topic, QoS::AtLeastOnce).await?;
+    let identity = Identity::new(
+        topics::room_filter(&config.room_id, config.all_rooms),
+        Sender::system("summarizer"),
+        "Summarizer - generates conversation summaries for context management",
+    );
+    let mqtt = MqttSettings {
+        host: config.mqtt_host.clone(),
+        port: config.mqtt_port,
+        client_id: "summarizer".to_string(),
+        keep_alive_secs: 30,
+    };
 
-    info!("Subscribed to: {}", public_topic);
+    info!("Summarizer running");
 
-    // Initialize state
-    let message_history = Arc::new(Mutex::new(MessageHistory::new(1000))); // Keep more history for summarization
-    let last_summary_ts = Arc::new(Mutex::new(0u64));
-    let summary_text = Arc::new(Mutex::new(String::new()));
-    let message_count_since_summary = Arc::new(Mutex::new(0u64));
+    Runtime::new(identity, mqtt)
+        .run(Summarizer::new(config))
+        .await?;
+    Ok(())
+}
 
-    let llm_client = SummarizerLlm::new(
-        config.openai_api_key.clone(),
-        config.openai_model.clone(),
-        Some(config.openai_base_url.clone()),
-    );
+struct Summarizer {
+    config: SummarizerConfig,
+    llm_client: SummarizerLlm,
+    rooms: HashMap<String, RoomState>,
+}
 
-    // Spawn heartbeat task
-    let client_clone = client.clone();
-    let room_id = config.room_id.clone();
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(10));
-        let mut counter = 0u64;
-        loop {
-            interval.tick().await;
-            counter += 1;
-            let now = now_secs();
+/// Summarization progress for one room
+struct RoomState {
+    message_history: MessageHistory,
+    last_summary_ts: u64,
+    summary_text: String,
+    message_count_since_summary: u64,
+}
 
-            let payload = if counter % 3 == 0 {
-                HeartbeatPayload {
-                    ts: now,
-                    description: Some(
-                        "Summarizer - generates conversation summaries for context management"
-                            .to_string(),
-                    ),
-                    can_accept_tasks: false,
-                }
-            } else {
-                HeartbeatPayload {
-                    ts: now,
-                    description: None,
-                    can_accept_tasks: false,
-                }
-            };
+impl RoomState {
+    fn new() -> Self {
+        Self {
+            message_history: MessageHistory::new(1000), // Keep more history for summarization
+            last_summary_ts: 0,
+            summary_text: String::new(),
+            message_count_since_summary: 0,
+        }
+    }
+}
 
-            let heartbeat = Envelope {
-                id: format!("summarizer_heartbeat_{}", counter),
-                message_type: EnvelopeType::Heartbeat,
-                room_id: room_id.clone(),
-                from: Sender {
-                    kind: SenderKind::System,
-                    id: "summarizer".to_string(),
-                },
-                ts: now,
-                payload: serde_json::to_value(payload).unwrap(),
-            };
-            let topic = format!("rooms/{}/agents/summarizer/heartbeat", room_id);
-            let _ = client_clone
-                .publish(
-                    topic,
-                    QoS::AtLeastOnce,
-                    false,
-                    serde_json::to_vec(&heartbeat).unwrap(),
-                )
-                .await;
+impl Summarizer {
+    fn new(config: SummarizerConfig) -> Self {
+        let llm_client = SummarizerLlm::new(
+            config.openai_api_key.clone(),
+            config.openai_model.clone(),
+            Some(config.openai_base_url.clone()),
+        );
+
+        Self {
+            llm_client,
+            rooms: HashMap::new(),
+            config,
         }
-    });
+    }
+}
 
-    info!("Summarizer running");
+impl Component for Summarizer {
+    fn subscriptions(&self) -> Vec<String> {
+        vec![topics::public(topics::room_filter(
+            &self.config.room_id,
+            self.config.all_rooms,
+        ))]
+    }
 
-    // Main event loop
-    loop {
-        match event_loop.poll().await {
-            Ok(Event::Incoming(Packet::Publish(p))) => {
-                if p.topic == public_topic {
-                    handle_public_message(
-                        &p.payload,
-                        &config,
-                        &client,
-                        &message_history,
-                        &last_summary_ts,
-                        &summary_text,
-                        &message_count_since_summary,
-                        &llm_client,
-                    )
-                    .await;
-                }
+    async fn on_envelope(&mut self, ctx: &Context, topic: &str, envelope: Envelope) {
+        match Topic::parse(topic) {
+            Ok(Topic::Public { room_id }) => {
+                self.handle_public_message(ctx, room_id, envelope).await
             }
             Ok(_) => {}
-            Err(e) => {
-                error!("MQTT error: {}", e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            }
+            Err(e) => warn!("{}", e),
         }
     }
 }
 
-async fn handle_public_message(
-    payload: &[u8],
-    config: &SummarizerConfig,
-    client: &AsyncClient,
-    message_history: &Arc<Mutex<MessageHistory>>,
-    last_summary_ts: &Arc<Mutex<u64>>,
-    summary_text: &Arc<Mutex<String>>,
-    message_count_since_summary: &Arc<Mutex<u64>>,
-    llm_client: &SummarizerLlm,
-) {
-    // Parse envelope
-    let Ok(envelope) = serde_json::from_slice::<Envelope>(payload) else {
-        return;
-    };
+impl Summarizer {
+    async fn handle_public_message(&mut self, ctx: &Context, room_id: &str, envelope: Envelope) {
+        // Skip summary messages (don't summarize summaries)
+        if matches!(envelope.payload, Payload::Summary(_)) {
+            return;
+        }
 
-    // Skip summary messages (don't summarize summaries)
-    if envelope.message_type == EnvelopeType::Summary {
-        return;
-    }
+        let room = self
+            .rooms
+            .entry(room_id.to_string())
+            .or_insert_with(RoomState::new);
 
-    // Add to history
-    {
-        let mut history = message_history.lock().await;
-        history.add(envelope.clone());
-    }
+        // Add to history
+        room.message_history.add(envelope.clone());
 
-    // Only trigger summarization on Result messages (task completion)
-    if envelope.message_type != EnvelopeType::Result {
-        return;
-    }
+        // Only trigger summarization on Result messages (task completion)
+        let Payload::Result(result_payload) = &envelope.payload else {
+            return;
+        };
 
-    // Check if this is actually a result (not ack)
-    if let Ok(result_payload) = serde_json::from_value::<ResultPayload>(envelope.payload.clone()) {
-        use common::ResultMessageType;
+        // Check if this is actually a result (not ack)
         if !matches!(result_payload.message_type, ResultMessageType::Result) {
             // Skip acks and other non-result messages
             return;
         }
-    } else {
-        return;
-    }
 
-    // Increment counter
-    let mut count = message_count_since_summary.lock().await;
-    *count += 1;
+        // Increment counter
+        room.message_count_since_summary += 1;
 
-    // Check if we should generate a summary
-    if *count >= config.summary_interval {
-        info!("Reached {} completed tasks, generating summary...", *count);
+        // Check if we should generate a summary
+        if room.message_count_since_summary < self.config.summary_interval {
+            return;
+        }
+
+        info!(
+            "Reached {} completed tasks in {}, generating summary...",
+            room.message_count_since_summary, room_id
+        );
 
         // Get messages since last summary
-        let messages_to_summarize = {
-            let history = message_history.lock().await;
-            history
-                .to_chat_messages()
-                .into_iter()
-                .filter(|_msg| {
-                    // Since we don't have timestamp in ChatMessage, we keep all for now
-                    // For now, include all messages (proper filtering would need message metadata)
-                    true
-                })
-                .collect::<Vec<_>>()
-        };
+        // Since we don't have timestamp in ChatMessage, we keep all for now
+        // (proper filtering would need message metadata)
+        let messages_to_summarize = room.message_history.to_chat_messages();
 
         if messages_to_summarize.is_empty() {
             warn!("No messages to summarize");
@@ -204,18 +160,16 @@ async fn handle_public_message(
         }
 
         // Get previous summary
-        let previous_summary = {
-            let summary = summary_text.lock().await;
-            if summary.is_empty() {
-                None
-            } else {
-                Some(summary.clone())
-            }
+        let previous_summary = if room.summary_text.is_empty() {
+            None
+        } else {
+            Some(room.summary_text.as_str())
         };
 
         // Generate new summary
-        match llm_client
-            .generate_summary(previous_summary.as_deref(), &messages_to_summarize)
+        match self
+            .llm_client
+            .generate_summary(previous_summary, &messages_to_summarize)
             .await
         {
             Ok(new_summary) => {
@@ -224,47 +178,23 @@ async fn handle_public_message(
                 let now = now_secs();
 
                 // Update state
-                {
-                    let mut summary = summary_text.lock().await;
-                    *summary = new_summary.clone();
-                }
-                {
-                    let mut ts = last_summary_ts.lock().await;
-                    *ts = envelope.ts; // Use timestamp of latest message
-                }
-                *count = 0; // Reset counter
+                room.summary_text = new_summary.clone();
+                room.last_summary_ts = envelope.ts; // Use timestamp of latest message
+                room.message_count_since_summary = 0; // Reset counter
 
                 // Publish summary
-                let summary_envelope = Envelope {
-                    id: format!("summary_{}", now),
-                    message_type: EnvelopeType::Summary,
-                    room_id: config.room_id.clone(),
-                    from: Sender {
-                        kind: SenderKind::System,
-                        id: "summarizer".to_string(),
-                    },
-                    ts: now,
-                    payload: serde_json::to_value(SummaryPayload {
-                        summary_text: new_summary,
-                        covers_until_ts: envelope.ts,
-                        message_count: config.summary_interval,
-                        generated_at: now,
-                    })
-                    .unwrap(),
-                };
-
-                if let Err(e) = client
-                    .publish(
-                        topics::summary(&config.room_id),
-                        QoS::AtLeastOnce,
-                        false,
-                        serde_json::to_vec(&summary_envelope).unwrap(),
-                    )
-                    .await
-                {
+                let summary_envelope = ctx.envelopes_in(room_id).summary(SummaryPayload {
+                    summary_text: new_summary,
+                    covers_until_ts: envelope.ts / 1000,
+                    message_count: self.config.summary_interval,
+                    generated_at: now,
+                });
+
+                let summary_topic = topics::summary(room_id);
+                if let Err(e) = ctx.publish(summary_topic.clone(), &summary_envelope).await {
                     error!("Failed to publish summary: {}", e);
                 } else {
-                    info!("Summary published to {}", topics::summary(&config.room_id));
+                    info!("Summary published to {}", summary_topic);
                 }
             }
             Err(e) => {
@@ -273,10 +203,3 @@ async fn handle_public_message(
         }
     }
 }
-
-fn now_secs() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-}
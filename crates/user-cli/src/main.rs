@@ -1,10 +1,13 @@
 use chrono::{DateTime, Local};
 use clap::Parser;
 use common::message::{
-    Envelope, EnvelopeType, HeartbeatPayload, ResultContent, ResultPayload, SayPayload, Sender,
+    Envelope, ModerationDecision, ModerationDecisionPayload, Payload, ResultContent, Sender,
     SenderKind, SummaryPayload,
 };
-use common::topics;
+use common::names::{is_valid_id, RoomId};
+use common::signing::Signer;
+use common::time::now_secs;
+use common::topics::{self, Topic};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
     execute,
@@ -32,7 +35,7 @@ use tracing::error;
 struct Args {
     /// Room ID to join (optional, will prompt if not provided)
     #[arg(long, env = "ROOM_ID")]
-    room_id: Option<String>,
+    room_id: Option<RoomId>,
 
     /// User ID (your username, optional, will prompt if not provided)
     #[arg(long, env = "USER_ID")]
@@ -45,6 +48,15 @@ struct Args {
     /// MQTT broker port
     #[arg(long, env = "MQTT_PORT", default_value = "1883")]
     mqtt_port: u16,
+
+    /// Join as a moderator: see held messages and /approve or /deny them
+    #[arg(long, env = "MODERATOR")]
+    moderator: bool,
+
+    /// Base64 Ed25519 secret key file used to sign moderation decisions;
+    /// requires --user-id
+    #[arg(long, env = "SIGNING_KEY", requires = "user_id")]
+    signing_key: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -174,16 +186,14 @@ impl App {
     }
 }
 
-fn now_secs() -> u64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-}
-
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
+    // Loaded before the terminal is taken over so a bad key path is readable
+    let signer = match (&args.signing_key, &args.user_id) {
+        (Some(path), Some(user_id)) => Some(Signer::load(user_id, path)?),
+        _ => None,
+    };
 
     // Setup terminal for welcome screen
     enable_raw_mode()?;
@@ -193,10 +203,12 @@ async fn main() -> anyhow::Result<()> {
     let mut terminal = Terminal::new(backend)?;
 
     // Show welcome screen and get room ID and username
-    let (room_id, user_id) = if args.room_id.is_some() && args.user_id.is_some() {
-        (args.room_id.unwrap(), args.user_id.unwrap())
+    let (room_id, user_id) = if let (Some(room_id), Some(user_id)) = (&args.room_id, &args.user_id)
+    {
+        (room_id.to_string(), user_id.clone())
     } else {
-        match show_welcome_screen(&mut terminal, args.room_id, args.user_id).await {
+        let default_room = args.room_id.map(String::from);
+        match show_welcome_screen(&mut terminal, default_room, args.user_id).await {
             Ok((room, user)) => (room, user),
             Err(e) => {
                 // Restore terminal
@@ -220,14 +232,23 @@ async fn main() -> anyhow::Result<()> {
     mqttoptions.set_keep_alive(Duration::from_secs(10));
 
     let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
-    let public_topic = format!("rooms/{}/public", room_id);
-    let heartbeat_topic = format!("rooms/{}/agents/+/heartbeat", room_id);
+    let public_topic = topics::public(&room_id);
+    let heartbeat_topic = topics::all_agent_heartbeats(&room_id);
     let summary_topic = topics::summary(&room_id);
+    let control_topic = topics::control(&room_id);
 
-    // Subscribe to public channel, agent heartbeats, and summaries
+    // Subscribe to public channel, agent heartbeats, summaries and control
     client.subscribe(&public_topic, QoS::AtLeastOnce).await?;
     client.subscribe(&heartbeat_topic, QoS::AtLeastOnce).await?;
     client.subscribe(&summary_topic, QoS::AtLeastOnce).await?;
+    client.subscribe(&control_topic, QoS::AtLeastOnce).await?;
+    // Held messages aren't public until approved, so only moderators see them
+    let moderator = args.moderator;
+    if moderator {
+        client
+            .subscribe(topics::moderation(&room_id), QoS::AtLeastOnce)
+            .await?;
+    }
 
     // Create app state
     let app = Arc::new(Mutex::new(App::new(room_id.clone(), user_id.clone())));
@@ -235,7 +256,7 @@ async fn main() -> anyhow::Result<()> {
 
     // Spawn MQTT event loop in background
     tokio::spawn(async move {
-        handle_mqtt_events(&mut eventloop, app_clone).await;
+        handle_mqtt_events(&mut eventloop, app_clone, moderator).await;
     });
 
     // Spawn background task to clean up stale agents
@@ -250,7 +271,16 @@ async fn main() -> anyhow::Result<()> {
     });
 
     // Main UI loop
-    let res = run_app(&mut terminal, app, client, room_id, user_id).await;
+    let res = run_app(
+        &mut terminal,
+        app,
+        client,
+        room_id,
+        user_id,
+        moderator,
+        signer,
+    )
+    .await;
 
     // Restore terminal
     disable_raw_mode()?;
@@ -274,6 +304,8 @@ async fn run_app(
     client: AsyncClient,
     room_id: String,
     user_id: String,
+    moderator: bool,
+    signer: Option<Signer>,
 ) -> anyhow::Result<()> {
     loop {
         // Draw UI
@@ -299,7 +331,12 @@ async fn run_app(
                     }
                     KeyCode::Enter => {
                         let msg = app_lock.input.clone();
-                        if !msg.is_empty() {
+                        if let Some(decision) = parse_moderation_command(&msg).filter(|_| moderator)
+                        {
+                            send_decision(&client, &room_id, &user_id, signer.as_ref(), decision)
+                                .await?;
+                            app_lock.clear_input();
+                        } else if !msg.is_empty() {
                             send_message(&client, &room_id, &user_id, msg).await?;
                             app_lock.clear_input();
                         }
@@ -664,19 +701,53 @@ async fn send_message(
     user_id: &str,
     text: String,
 ) -> anyhow::Result<()> {
-    let envelope = Envelope {
-        id: format!("user_msg_{}", now_secs()),
-        message_type: EnvelopeType::Say,
-        room_id: room_id.to_string(),
-        from: Sender {
-            kind: SenderKind::User,
-            id: user_id.to_string(),
-        },
-        ts: now_secs(),
-        payload: serde_json::to_value(SayPayload { text })?,
+    let envelope = Envelope::builder(room_id, Sender::user(user_id)).say(text);
+
+    let topic = topics::public(room_id);
+    let payload = serde_json::to_string(&envelope)?;
+
+    client
+        .publish(topic, QoS::AtLeastOnce, false, payload)
+        .await?;
+
+    Ok(())
+}
+
+/// Parse `/approve <message_id>` or `/deny <message_id> [reason]`
+fn parse_moderation_command(input: &str) -> Option<ModerationDecisionPayload> {
+    let mut words = input.trim().splitn(3, ' ');
+    let decision = match words.next()? {
+        "/approve" => ModerationDecision::Approve,
+        "/deny" => ModerationDecision::Deny,
+        _ => return None,
     };
+    let message_id = words.next().filter(|id| !id.is_empty())?.to_string();
+    let reason = words
+        .next()
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .map(String::from);
+    Some(ModerationDecisionPayload {
+        message_id,
+        decision,
+        reason,
+    })
+}
+
+async fn send_decision(
+    client: &AsyncClient,
+    room_id: &str,
+    user_id: &str,
+    signer: Option<&Signer>,
+    decision: ModerationDecisionPayload,
+) -> anyhow::Result<()> {
+    let mut envelope =
+        Envelope::builder(room_id, Sender::user(user_id)).moderation_decision(decision);
+    if let Some(signer) = signer {
+        signer.sign(&mut envelope);
+    }
 
-    let topic = format!("rooms/{}/public", room_id);
+    let topic = topics::moderation(room_id);
     let payload = serde_json::to_string(&envelope)?;
 
     client
@@ -686,22 +757,24 @@ async fn send_message(
     Ok(())
 }
 
-async fn handle_mqtt_events(eventloop: &mut EventLoop, app: Arc<Mutex<App>>) {
+async fn handle_mqtt_events(eventloop: &mut EventLoop, app: Arc<Mutex<App>>, moderator: bool) {
     loop {
         match eventloop.poll().await {
-            Ok(MqttEvent::Incoming(Packet::Publish(p))) => {
-                if let Ok(text) = String::from_utf8(p.payload.to_vec()) {
-                    if let Ok(envelope) = serde_json::from_str::<Envelope>(&text) {
-                        if p.topic.ends_with("/heartbeat") {
-                            process_heartbeat(&envelope, &app).await;
-                        } else if p.topic.ends_with("/summary") {
-                            process_message(envelope, &app).await;
-                        } else {
+            Ok(MqttEvent::Incoming(Packet::Publish(p))) => match Envelope::from_slice(&p.payload) {
+                Ok(envelope) => match Topic::parse(&p.topic) {
+                    Ok(Topic::Heartbeat { .. }) => process_heartbeat(&envelope, &app).await,
+                    // Only mic expiry notices are shown from the control topic
+                    Ok(Topic::Control { .. }) => {
+                        if matches!(envelope.payload, Payload::MicExpired(_)) {
                             process_message(envelope, &app).await;
                         }
                     }
-                }
-            }
+                    Ok(Topic::Moderation { .. }) if !moderator => {}
+                    Ok(_) => process_message(envelope, &app).await,
+                    Err(e) => error!("{}", e),
+                },
+                Err(e) => error!("Invalid envelope on {}: {}", p.topic, e),
+            },
             Ok(_) => {}
             Err(e) => {
                 error!("MQTT error: {}", e);
@@ -712,129 +785,171 @@ async fn handle_mqtt_events(eventloop: &mut EventLoop, app: Arc<Mutex<App>>) {
 }
 
 async fn process_heartbeat(envelope: &Envelope, app: &Arc<Mutex<App>>) {
-    if envelope.message_type == EnvelopeType::Heartbeat {
-        if serde_json::from_value::<HeartbeatPayload>(envelope.payload.clone()).is_ok() {
-            let agent_id = envelope.from.id.clone();
-            let mut app_lock = app.lock().await;
-
-            // Update or create agent entry
-            app_lock
-                .agents
-                .entry(agent_id)
-                .and_modify(|status| {
-                    status.last_updated = envelope.ts;
-                })
-                .or_insert(AgentStatus {
-                    state: AgentState::Idle,
-                    last_updated: envelope.ts,
-                });
-        }
+    if let Payload::Heartbeat(_) = envelope.payload {
+        let agent_id = envelope.from.id.clone();
+        let mut app_lock = app.lock().await;
+
+        // Update or create agent entry
+        app_lock
+            .agents
+            .entry(agent_id)
+            .and_modify(|status| {
+                status.last_updated = envelope.ts / 1000;
+            })
+            .or_insert(AgentStatus {
+                state: AgentState::Idle,
+                last_updated: envelope.ts / 1000,
+            });
     }
 }
 
 async fn process_message(envelope: Envelope, app: &Arc<Mutex<App>>) {
     let sender_id = envelope.from.id.clone();
     let sender_kind = envelope.from.kind.clone();
-    let timestamp = DateTime::from_timestamp(envelope.ts as i64, 0)
+    let timestamp = DateTime::from_timestamp_millis(envelope.ts as i64)
         .map(|dt| dt.with_timezone(&Local))
-        .unwrap_or_else(|| Local::now());
+        .unwrap_or_else(Local::now);
     let is_agent = sender_kind == SenderKind::Agent;
 
-    let (msg_type, content) = match envelope.message_type {
-        EnvelopeType::Say => {
-            if let Ok(say) = serde_json::from_value::<SayPayload>(envelope.payload.clone()) {
-                ("Say".to_string(), say.text)
-            } else {
-                ("Say".to_string(), "[invalid]".to_string())
-            }
-        }
-        EnvelopeType::Summary => {
-            if let Ok(summary) = serde_json::from_value::<SummaryPayload>(envelope.payload.clone())
-            {
-                // Update the current summary in app state
-                let mut app_lock = app.lock().await;
-                app_lock.current_summary = Some(summary.clone());
-                drop(app_lock);
-            }
+    let (msg_type, content) = match &envelope.payload {
+        Payload::Say(say) => ("Say".to_string(), say.text.clone()),
+        Payload::Summary(summary) => {
+            // Update the current summary in app state
+            let mut app_lock = app.lock().await;
+            app_lock.current_summary = Some(summary.clone());
+            drop(app_lock);
             // Don't add summary to message stream - it will be shown in dedicated panel
             return;
         }
-        EnvelopeType::Result => {
-            if let Ok(result) = serde_json::from_value::<ResultPayload>(envelope.payload.clone()) {
-                let msg_type_str = result.message_type.to_string();
-
-                // Update agent state for ALL agents (including facilitator)
-                if is_agent {
-                    match msg_type_str.as_str() {
-                        "ack" => {
-                            let mut app_lock = app.lock().await;
-                            // Ensure agent exists (in case heartbeat hasn't arrived yet)
-                            app_lock
-                                .agents
-                                .entry(sender_id.clone())
-                                .or_insert(AgentStatus {
-                                    state: AgentState::Idle,
-                                    last_updated: envelope.ts,
-                                });
-
-                            app_lock.update_agent(
-                                sender_id.clone(),
-                                AgentState::Working {
-                                    task_id: result.task_id.clone(),
-                                },
-                                envelope.ts,
-                            );
+        Payload::MicExpired(expired) => {
+            // The agent can no longer speak on this task
+            let mut app_lock = app.lock().await;
+            let working_on_task = app_lock
+                .agents
+                .get(&expired.agent_id)
+                .is_some_and(|status| {
+                    status.state
+                        == AgentState::Working {
+                            task_id: expired.task_id.clone(),
                         }
-                        "result" => {
-                            let mut app_lock = app.lock().await;
-                            // Ensure agent exists
-                            app_lock
-                                .agents
-                                .entry(sender_id.clone())
-                                .or_insert(AgentStatus {
-                                    state: AgentState::Idle,
-                                    last_updated: envelope.ts,
-                                });
-
-                            app_lock.update_agent(
-                                sender_id.clone(),
-                                AgentState::Complete {
-                                    task_id: result.task_id.clone(),
-                                },
-                                envelope.ts,
-                            );
-                            drop(app_lock);
-
-                            // Set back to idle after a moment
-                            let sender_clone = sender_id.clone();
-                            let app_clone: Arc<Mutex<App>> = Arc::clone(app);
-                            tokio::spawn(async move {
-                                tokio::time::sleep(Duration::from_millis(500)).await;
-                                let mut app_lock = app_clone.lock().await;
-                                if let Some(status) = app_lock.agents.get(&sender_clone) {
-                                    if matches!(status.state, AgentState::Complete { .. }) {
-                                        app_lock.update_agent(
-                                            sender_clone,
-                                            AgentState::Idle,
-                                            now_secs(),
-                                        );
-                                    }
-                                }
+                });
+            if working_on_task {
+                app_lock.update_agent(
+                    expired.agent_id.clone(),
+                    AgentState::Idle,
+                    envelope.ts / 1000,
+                );
+            }
+            drop(app_lock);
+
+            (
+                "MicExpired".to_string(),
+                format!(
+                    "{} lost the mic for {} ({})",
+                    expired.agent_id, expired.task_id, expired.reason
+                ),
+            )
+        }
+        Payload::ModerationRequest(request) => {
+            let held = &request.message;
+            let content = match &held.payload {
+                Payload::Result(result) => extract_result_content(&result.content),
+                _ => String::new(),
+            };
+            (
+                "Held".to_string(),
+                format!(
+                    "{} from {} ({}): {} | /approve {} or /deny {}",
+                    held.id, held.from.id, request.reason, content, held.id, held.id
+                ),
+            )
+        }
+        Payload::ModerationDecision(decision) => {
+            let verdict = match decision.decision {
+                ModerationDecision::Approve => "approved",
+                ModerationDecision::Deny => "denied",
+            };
+            (
+                "Moderation".to_string(),
+                format!("{} {}", verdict, decision.message_id),
+            )
+        }
+        Payload::Result(result) => {
+            let msg_type_str = result.message_type.to_string();
+
+            // Update agent state for ALL agents (including facilitator)
+            if is_agent {
+                match &result.content {
+                    ResultContent::Ack(_) => {
+                        let mut app_lock = app.lock().await;
+                        // Ensure agent exists (in case heartbeat hasn't arrived yet)
+                        app_lock
+                            .agents
+                            .entry(sender_id.clone())
+                            .or_insert(AgentStatus {
+                                state: AgentState::Idle,
+                                last_updated: envelope.ts / 1000,
                             });
-                        }
-                        _ => {}
+
+                        app_lock.update_agent(
+                            sender_id.clone(),
+                            AgentState::Working {
+                                task_id: result.task_id.clone(),
+                            },
+                            envelope.ts / 1000,
+                        );
+                    }
+                    ResultContent::Result(_) => {
+                        let mut app_lock = app.lock().await;
+                        // Ensure agent exists
+                        app_lock
+                            .agents
+                            .entry(sender_id.clone())
+                            .or_insert(AgentStatus {
+                                state: AgentState::Idle,
+                                last_updated: envelope.ts / 1000,
+                            });
+
+                        app_lock.update_agent(
+                            sender_id.clone(),
+                            AgentState::Complete {
+                                task_id: result.task_id.clone(),
+                            },
+                            envelope.ts / 1000,
+                        );
+                        drop(app_lock);
+
+                        // Set back to idle after a moment
+                        let sender_clone = sender_id.clone();
+                        let app_clone: Arc<Mutex<App>> = Arc::clone(app);
+                        tokio::spawn(async move {
+                            tokio::time::sleep(Duration::from_millis(500)).await;
+                            let mut app_lock = app_clone.lock().await;
+                            if let Some(status) = app_lock.agents.get(&sender_clone) {
+                                if matches!(status.state, AgentState::Complete { .. }) {
+                                    app_lock.update_agent(
+                                        sender_clone,
+                                        AgentState::Idle,
+                                        now_secs(),
+                                    );
+                                }
+                            }
+                        });
                     }
+                    _ => {}
                 }
+            }
 
-                let content = extract_result_content(&result.content);
-                (format!("Result:{}", msg_type_str), content)
-            } else {
-                ("Result".to_string(), "[invalid]".to_string())
+            let mut content = extract_result_content(&result.content);
+            if !result.redactions.is_empty() {
+                content.push_str(" (redacted)");
             }
+            (format!("Result:{}", msg_type_str), content)
         }
         _ => {
-            let msg_type = format!("{:?}", envelope.message_type);
-            let content = serde_json::to_string(&envelope.payload)
+            let msg_type = format!("{:?}", envelope.message_type());
+            let content = serde_json::to_value(&envelope.payload)
+                .and_then(|v| serde_json::to_string(&v["payload"]))
                 .unwrap_or_else(|_| "[invalid]".to_string());
             (msg_type, content)
         }
@@ -999,10 +1114,8 @@ async fn show_welcome_screen(
                     KeyCode::Tab => {
                         active_field = if active_field == 0 { 1 } else { 0 };
                     }
-                    KeyCode::Enter => {
-                        if !room_input.is_empty() && !user_input.is_empty() {
-                            return Ok((room_input, user_input));
-                        }
+                    KeyCode::Enter if is_valid_id(&room_input) && !user_input.is_empty() => {
+                        return Ok((room_input, user_input));
                     }
                     KeyCode::Char(c) => {
                         if active_field == 0 {